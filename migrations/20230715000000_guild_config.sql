@@ -0,0 +1,26 @@
+-- Per-guild leveling settings. Defaults here must match GuildConfig::default().
+CREATE TABLE guild_config (
+    id BIGINT NOT NULL PRIMARY KEY,
+    cooldown BIGINT NOT NULL DEFAULT 60,
+    min_xp BIGINT NOT NULL DEFAULT 15,
+    max_xp BIGINT NOT NULL DEFAULT 25
+);
+
+-- Tells every minixpd process when a guild's config changes, so they can drop their cached copy.
+-- The trigger argument is the column holding the guild ID.
+CREATE FUNCTION notify_config_changed() RETURNS trigger AS $$
+DECLARE
+    guild TEXT;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        guild := to_jsonb(OLD) ->> TG_ARGV[0];
+    ELSE
+        guild := to_jsonb(NEW) ->> TG_ARGV[0];
+    END IF;
+    PERFORM pg_notify('config_changed', guild);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER guild_config_changed AFTER INSERT OR UPDATE OR DELETE ON guild_config
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('id');
@@ -0,0 +1,6 @@
+-- level_up_mode is one of 'off', 'current', 'channel' or 'dm'.
+-- A NULL level_up_message means the built-in template is used.
+ALTER TABLE guild_config
+    ADD COLUMN level_up_mode VARCHAR(16) NOT NULL DEFAULT 'off',
+    ADD COLUMN level_up_channel BIGINT,
+    ADD COLUMN level_up_message TEXT;
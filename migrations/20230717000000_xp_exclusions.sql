@@ -0,0 +1,16 @@
+CREATE TABLE excluded_channels (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    PRIMARY KEY (guild, id)
+);
+
+CREATE TABLE excluded_roles (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    PRIMARY KEY (guild, id)
+);
+
+CREATE TRIGGER excluded_channels_changed AFTER INSERT OR UPDATE OR DELETE ON excluded_channels
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('guild');
+CREATE TRIGGER excluded_roles_changed AFTER INSERT OR UPDATE OR DELETE ON excluded_roles
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('guild');
@@ -0,0 +1,18 @@
+CREATE TABLE role_multipliers (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    multiplier DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (guild, id)
+);
+
+CREATE TABLE channel_multipliers (
+    id BIGINT NOT NULL,
+    guild BIGINT NOT NULL,
+    multiplier DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (guild, id)
+);
+
+CREATE TRIGGER role_multipliers_changed AFTER INSERT OR UPDATE OR DELETE ON role_multipliers
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('guild');
+CREATE TRIGGER channel_multipliers_changed AFTER INSERT OR UPDATE OR DELETE ON channel_multipliers
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('guild');
@@ -0,0 +1,23 @@
+-- Every change to levels.xp, newest first. Old events get rolled up into xp_event_summaries.
+CREATE TABLE xp_events (
+    event_id BIGSERIAL PRIMARY KEY,
+    guild BIGINT NOT NULL,
+    id BIGINT NOT NULL,
+    delta BIGINT NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX xp_events_guild_time ON xp_events (guild, created_at);
+CREATE INDEX xp_events_user_time ON xp_events (guild, id, created_at);
+
+-- One row per user, day and source, for events past the retention window.
+CREATE TABLE xp_event_summaries (
+    guild BIGINT NOT NULL,
+    id BIGINT NOT NULL,
+    day DATE NOT NULL,
+    source VARCHAR(16) NOT NULL,
+    delta BIGINT NOT NULL,
+    events BIGINT NOT NULL,
+    PRIMARY KEY (guild, id, day, source)
+);
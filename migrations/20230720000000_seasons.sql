@@ -0,0 +1,24 @@
+-- Finished seasons, and everyone's XP at the moment each one ended.
+CREATE TABLE seasons (
+    guild BIGINT NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    ended_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (guild, name)
+);
+
+CREATE TABLE season_levels (
+    guild BIGINT NOT NULL,
+    season VARCHAR(64) NOT NULL,
+    id BIGINT NOT NULL,
+    xp BIGINT NOT NULL,
+    PRIMARY KEY (guild, season, id),
+    FOREIGN KEY (guild, season) REFERENCES seasons (guild, name) ON DELETE CASCADE
+);
+
+-- Guilds that want their seasons to end by themselves.
+CREATE TABLE season_schedules (
+    guild BIGINT PRIMARY KEY,
+    every_days INT NOT NULL,
+    carry_over INT NOT NULL,
+    next_end TIMESTAMPTZ NOT NULL
+);
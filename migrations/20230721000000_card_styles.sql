@@ -0,0 +1,9 @@
+-- Rank card customization, per user. NULL means the default for that part of the card.
+CREATE TABLE card_styles (
+    id BIGINT PRIMARY KEY,
+    background VARCHAR(7),
+    foreground VARCHAR(7),
+    progress_bar VARCHAR(7),
+    text VARCHAR(7),
+    font VARCHAR(32)
+);
@@ -0,0 +1,23 @@
+-- A server's default rank card look. NULL means the normal default for that part of the card.
+-- With force on, members' own choices are ignored in this server.
+CREATE TABLE card_themes (
+    guild BIGINT PRIMARY KEY,
+    background VARCHAR(7),
+    foreground VARCHAR(7),
+    progress_bar VARCHAR(7),
+    text VARCHAR(7),
+    font VARCHAR(32),
+    force BOOLEAN NOT NULL DEFAULT false
+);
+
+-- If a server has any rows here, these are the only toys its members can use.
+CREATE TABLE allowed_toys (
+    guild BIGINT NOT NULL,
+    toy VARCHAR(64) NOT NULL,
+    PRIMARY KEY (guild, toy)
+);
+
+CREATE TRIGGER card_themes_changed AFTER INSERT OR UPDATE OR DELETE ON card_themes
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('guild');
+CREATE TRIGGER allowed_toys_changed AFTER INSERT OR UPDATE OR DELETE ON allowed_toys
+    FOR EACH ROW EXECUTE FUNCTION notify_config_changed('guild');
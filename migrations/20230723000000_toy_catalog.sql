@@ -0,0 +1,34 @@
+-- Every toy members can put on their card. The asset has to be one of the images the card
+-- renderer ships with. If allowed_users or allowed_roles has anything in it, only those users,
+-- or members with one of those roles, can pick the toy.
+CREATE TABLE toys (
+    name VARCHAR(64) PRIMARY KEY,
+    asset VARCHAR(64) NOT NULL,
+    level_requirement BIGINT,
+    allowed_users BIGINT[] NOT NULL DEFAULT '{}',
+    allowed_roles BIGINT[] NOT NULL DEFAULT '{}'
+);
+
+INSERT INTO toys (name, asset, level_requirement, allowed_users) VALUES
+    ('Airplane', 'airplane.png', NULL, '{788222689126776832, 526092507965161474}'),
+    ('Bee', 'bee.png', NULL, '{}'),
+    ('Biscuit', 'biscuit.png', NULL, '{}'),
+    ('Chicken', 'chicken.png', NULL, '{}'),
+    ('Cow', 'cow.png', NULL, '{}'),
+    ('Fox', 'fox.png', 5, '{}'),
+    ('Grass Block', 'grassblock.png', NULL, '{}'),
+    ('Parrot', 'parrot.png', 5, '{}'),
+    ('Pickaxe', 'pickaxe.png', 10, '{}'),
+    ('Pig', 'pig.png', NULL, '{}'),
+    ('Blue Potion', 'potion_blue.png', NULL, '{}'),
+    ('Purple Potion', 'potion_purple.png', NULL, '{}'),
+    ('Red Potion', 'potion_red.png', NULL, '{}'),
+    ('Sheep', 'sheep.png', NULL, '{}'),
+    ('Steve Hug', 'steveheart.png', NULL, '{}'),
+    ('Tree', 'tree.png', NULL, '{}');
+
+-- Picks used to be stored by filename. They're stored by name now, and "None" is just no row.
+UPDATE card_toy SET toy = toys.name FROM toys WHERE card_toy.toy = toys.asset;
+DELETE FROM card_toy WHERE toy NOT IN (SELECT name FROM toys);
+UPDATE allowed_toys SET toy = toys.name FROM toys WHERE allowed_toys.toy = toys.asset;
+DELETE FROM allowed_toys WHERE toy NOT IN (SELECT name FROM toys);
@@ -0,0 +1,5 @@
+-- A toy picked for every server at once. Picks in card_toy are per-server, and win over this one.
+CREATE TABLE global_toys (
+    id BIGINT PRIMARY KEY,
+    toy VARCHAR(64) NOT NULL
+);
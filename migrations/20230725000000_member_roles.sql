@@ -0,0 +1,9 @@
+-- Which roles each member has, kept up to date from the gateway so leaderboards can be filtered by role.
+CREATE TABLE member_roles (
+    guild BIGINT NOT NULL,
+    id BIGINT NOT NULL,
+    role BIGINT NOT NULL,
+    PRIMARY KEY (guild, id, role)
+);
+
+CREATE INDEX member_roles_by_role ON member_roles (guild, role);
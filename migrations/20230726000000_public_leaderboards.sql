@@ -0,0 +1,2 @@
+-- Whether anyone can see the guild's leaderboard on the web, without being in the guild.
+ALTER TABLE guild_config ADD COLUMN public_leaderboard BOOLEAN NOT NULL DEFAULT false;
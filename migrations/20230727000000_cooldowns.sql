@@ -0,0 +1,8 @@
+-- Cooldowns shared between processes, for COOLDOWN_BACKEND=postgres.
+-- Losing these in a crash just means a few people earn XP a little early, so skip the WAL.
+CREATE UNLOGGED TABLE cooldowns (
+    guild BIGINT NOT NULL,
+    id BIGINT NOT NULL,
+    expires TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (guild, id)
+);
@@ -0,0 +1,3 @@
+-- /config levels already refuses this, but a bad row would make rolling for XP panic.
+UPDATE guild_config SET min_xp = max_xp, max_xp = min_xp WHERE min_xp > max_xp;
+ALTER TABLE guild_config ADD CONSTRAINT xp_range CHECK (min_xp <= max_xp);
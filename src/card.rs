@@ -0,0 +1,767 @@
+use twilight_interactions::command::{CommandOption, CreateOption};
+use twilight_model::{
+    application::interaction::message_component::MessageComponentInteractionData,
+    channel::message::{
+        component::{ActionRow, Button, ButtonStyle},
+        Component, MessageFlags,
+    },
+    http::{
+        attachment::Attachment,
+        interaction::{InteractionResponse, InteractionResponseType},
+    },
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+    user::User,
+};
+use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
+use xpd_rank_card::colors::{self, Color, Colors};
+
+use crate::{
+    cmd_defs::{CardCommand, CardEdit, ThemeCommand, ThemeSet, ThemeToysCommand},
+    config::GuildConfig,
+    ephemeral_embed_response,
+    toy::CatalogToy,
+    AppState, Error,
+};
+
+/// Every button on the card preview starts with this, so dispatch can route it here.
+pub const CARD_ID_PREFIX: &str = "card_";
+const CANCEL_ID: &str = "card_cancel";
+const SAVE_PREFIX: &str = "card_save_";
+// WCAG's minimum for normal text. The progress bar isn't text, so it can get away with less.
+const MIN_TEXT_CONTRAST: f64 = 4.5;
+const MIN_PROGRESS_CONTRAST: f64 = 2.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CreateOption, CommandOption)]
+pub enum CardFont {
+    #[option(name = "Mojang", value = "mojang")]
+    Mojang,
+    #[option(name = "Roboto", value = "roboto")]
+    Roboto,
+    #[option(name = "JetBrains Mono", value = "jetbrains_mono")]
+    JetBrainsMono,
+    #[option(name = "Montserrat Alt1", value = "montserrat_alt1")]
+    MontserratAlt1,
+}
+
+impl CardFont {
+    pub fn from_value(value: &str) -> Option<Self> {
+        let font = match value {
+            "mojang" => Self::Mojang,
+            "roboto" => Self::Roboto,
+            "jetbrains_mono" => Self::JetBrainsMono,
+            "montserrat_alt1" => Self::MontserratAlt1,
+            _ => return None,
+        };
+        Some(font)
+    }
+    const fn font(self) -> xpd_rank_card::Font {
+        match self {
+            Self::Mojang => xpd_rank_card::Font::Mojang,
+            Self::Roboto => xpd_rank_card::Font::Roboto,
+            Self::JetBrainsMono => xpd_rank_card::Font::JetBrainsMono,
+            Self::MontserratAlt1 => xpd_rank_card::Font::MontserratAlt1,
+        }
+    }
+}
+
+/// How someone wants their rank card to look. Anything left as `None` uses the default.
+/// Colors are always stored as `#RRGGBB`.
+///
+/// The card has more colors than this, so each choice paints a few of them: the foreground is
+/// the border and the empty part of the progress bar, the progress bar color also colors the
+/// level number, and the text color is used for all the other text.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CardStyle {
+    pub background: Option<String>,
+    pub foreground: Option<String>,
+    pub progress_bar: Option<String>,
+    pub text: Option<String>,
+    pub font: Option<CardFont>,
+}
+
+impl CardStyle {
+    pub async fn load(user_id: Id<UserMarker>, db: &sqlx::PgPool) -> Result<Self, Error> {
+        #[allow(clippy::cast_possible_wrap)]
+        let style = query!(
+            "SELECT background, foreground, progress_bar, text, font FROM card_styles WHERE id = $1",
+            user_id.get() as i64
+        )
+        .fetch_optional(db)
+        .await?
+        .map(|v| Self {
+            background: v.background,
+            foreground: v.foreground,
+            progress_bar: v.progress_bar,
+            text: v.text,
+            font: v.font.as_deref().and_then(CardFont::from_value),
+        })
+        .unwrap_or_default();
+        Ok(style)
+    }
+    pub fn colors(&self) -> Colors {
+        let pick = |choice: &Option<String>, default: Color| {
+            choice
+                .as_ref()
+                .and_then(|hex| Color::from_hex(hex).ok())
+                .unwrap_or(default)
+        };
+        Colors {
+            important: pick(&self.text, colors::DEFAULT_IMPORTANT),
+            secondary: pick(&self.text, colors::DEFAULT_SECONDARY),
+            rank: pick(&self.text, colors::DEFAULT_RANK),
+            level: pick(&self.progress_bar, colors::DEFAULT_LEVEL),
+            border: pick(&self.foreground, colors::DEFAULT_BORDER),
+            background: pick(&self.background, colors::DEFAULT_BACKGROUND),
+            progress_foreground: pick(&self.progress_bar, colors::DEFAULT_PROGRESS_FOREGROUND),
+            progress_background: pick(&self.foreground, colors::DEFAULT_PROGRESS_BACKGROUND),
+        }
+    }
+    /// What this style looks like in a guild. The guild's theme fills in anything left as the
+    /// default, or replaces everything if the guild forces it. Both are checked for contrast
+    /// when they're saved, but mixing them might not be readable, and then the theme is left out.
+    pub fn themed(&self, config: &GuildConfig) -> Self {
+        let theme = &config.card_theme;
+        if config.force_card_theme {
+            return theme.clone();
+        }
+        let pick = |mine: &Option<String>, theirs: &Option<String>| {
+            mine.as_ref().or(theirs.as_ref()).cloned()
+        };
+        let mixed = Self {
+            background: pick(&self.background, &theme.background),
+            foreground: pick(&self.foreground, &theme.foreground),
+            progress_bar: pick(&self.progress_bar, &theme.progress_bar),
+            text: pick(&self.text, &theme.text),
+            font: self.font.or(theme.font),
+        };
+        if mixed.contrast_problem().is_some() {
+            return self.clone();
+        }
+        mixed
+    }
+    /// Returns this style with new picks applied, in the order background, foreground,
+    /// progress bar, text. Anything not picked stays how it was. If a color isn't valid hex,
+    /// that input is the error.
+    fn edited(&self, colors: [Option<String>; 4], font: Option<CardFont>) -> Result<Self, String> {
+        let [background, foreground, progress_bar, text] = colors;
+        let pick = |input: Option<String>, current: &Option<String>| {
+            input.map_or_else(
+                || Ok(current.clone()),
+                |input| parse_color(&input).map(Some).ok_or(input),
+            )
+        };
+        Ok(Self {
+            background: pick(background, &self.background)?,
+            foreground: pick(foreground, &self.foreground)?,
+            progress_bar: pick(progress_bar, &self.progress_bar)?,
+            text: pick(text, &self.text)?,
+            font: font.or(self.font),
+        })
+    }
+    pub fn font(&self) -> xpd_rank_card::Font {
+        self.font
+            .map_or(xpd_rank_card::Font::Mojang, CardFont::font)
+    }
+    /// Returns what's wrong if the text, level or progress bar would be hard to see.
+    fn contrast_problem(&self) -> Option<Error> {
+        let colors = self.colors();
+        let checks = [
+            (colors.important, colors.background, MIN_TEXT_CONTRAST),
+            // The level shares the progress bar's color, so this also makes sure a full bar,
+            // which covers its whole track, stands out from the background.
+            (colors.level, colors.background, MIN_TEXT_CONTRAST),
+            (
+                colors.progress_foreground,
+                colors.progress_background,
+                MIN_PROGRESS_CONTRAST,
+            ),
+        ];
+        checks.into_iter().find_map(|(front, back, minimum)| {
+            let ratio = contrast(front, back);
+            (ratio < minimum)
+                .then(|| Error::LowContrast(front.to_string(), back.to_string(), ratio, minimum))
+        })
+    }
+    // Button IDs are all we get back when someone clicks save, so the whole style goes in one,
+    // like `card_save_#112233.#445566...roboto`. Empty parts are defaults.
+    fn to_custom_id(&self) -> String {
+        let parts = [
+            self.background.as_deref().unwrap_or_default(),
+            self.foreground.as_deref().unwrap_or_default(),
+            self.progress_bar.as_deref().unwrap_or_default(),
+            self.text.as_deref().unwrap_or_default(),
+            self.font.map_or("", |font| font.value()),
+        ];
+        format!("{SAVE_PREFIX}{}", parts.join("."))
+    }
+    fn from_custom_id(custom_id: &str) -> Option<Self> {
+        let mut parts = custom_id.strip_prefix(SAVE_PREFIX)?.split('.');
+        let mut color = || parts.next().map(parse_color);
+        let style = Self {
+            background: color()?,
+            foreground: color()?,
+            progress_bar: color()?,
+            text: color()?,
+            font: None,
+        };
+        let font = parts.next()?;
+        Some(Self {
+            font: CardFont::from_value(font),
+            ..style
+        })
+    }
+    fn describe(&self) -> String {
+        let show = |choice: &Option<String>| choice.as_deref().unwrap_or("default").to_string();
+        format!(
+            "Background: `{}`\nForeground: `{}`\nProgress bar: `{}`\nText: `{}`\nFont: {}",
+            show(&self.background),
+            show(&self.foreground),
+            show(&self.progress_bar),
+            show(&self.text),
+            self.font()
+        )
+    }
+}
+
+pub async fn process_card(
+    cmd: CardCommand,
+    guild_id: Id<GuildMarker>,
+    invoker: User,
+    token: String,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    match cmd {
+        CardCommand::Edit(edit) => preview(edit, guild_id, invoker, token, state).await,
+        CardCommand::Reset(_) => {
+            #[allow(clippy::cast_possible_wrap)]
+            query!(
+                "DELETE FROM card_styles WHERE id = $1",
+                invoker.id.get() as i64
+            )
+            .execute(&state.db)
+            .await?;
+            let embed = EmbedBuilder::new()
+                .description("Your card is back to the default look.")
+                .color(crate::THEME_COLOR)
+                .build();
+            Ok(ephemeral_embed_response(embed))
+        }
+    }
+}
+
+// Nothing is saved until the preview's save button is clicked, and the button carries
+// the new style in its custom ID, so there's nothing to clean up if it never is.
+async fn preview(
+    edit: CardEdit,
+    guild_id: Id<GuildMarker>,
+    invoker: User,
+    token: String,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let config = state.configs.get(guild_id, &state.db).await?;
+    if config.force_card_theme {
+        return Err(Error::CardThemeForced);
+    }
+    let style = state
+        .metrics
+        .timed("card_style", CardStyle::load(invoker.id, &state.db))
+        .await?
+        .edited(
+            [
+                edit.background,
+                edit.foreground,
+                edit.progress_bar,
+                edit.text,
+            ],
+            edit.font,
+        )
+        .map_err(Error::InvalidColor)?;
+    // What gets saved is used in every server, so it has to be readable on its own.
+    if let Some(problem) = style.contrast_problem() {
+        return Err(problem);
+    }
+    let shown = style.themed(&config);
+    // Rendering takes a moment, so we answer later instead of making discord wait.
+    tokio::spawn(async move {
+        let sent = send_preview(&style, &shown, &config, guild_id, &invoker, &token, &state);
+        let Err(err) = sent.await else {
+            return;
+        };
+        let embed = EmbedBuilder::new().description(err.to_string()).build();
+        let embeds = [embed];
+        match state
+            .client
+            .interaction(state.my_id)
+            .create_followup(&token)
+            .embeds(&embeds)
+        {
+            Ok(awaitable) => {
+                if let Err(e) = awaitable.await {
+                    warn!("{e:#?}");
+                }
+            }
+            Err(e) => warn!("{e:#?}"),
+        }
+    });
+    Ok(InteractionResponse {
+        kind: InteractionResponseType::DeferredChannelMessageWithSource,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+        ),
+    })
+}
+
+// `style` is what gets saved, and `shown` is how it looks with the guild's theme applied.
+async fn send_preview(
+    style: &CardStyle,
+    shown: &CardStyle,
+    config: &GuildConfig,
+    guild_id: Id<GuildMarker>,
+    invoker: &User,
+    token: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    let (xp, rank) = crate::levels::xp_and_rank(guild_id, invoker.id, state).await?;
+    #[allow(clippy::cast_sign_loss)]
+    let level_info = mee6::LevelInfo::new(xp as u64);
+    let png = crate::levels::render_card(state, guild_id, config, invoker, level_info, rank, shown)
+        .await?;
+    let image = Attachment {
+        description: Some("A preview of your new rank card.".to_string()),
+        file: png,
+        filename: "preview.png".to_string(),
+        id: 0,
+    };
+    let save_button = Component::Button(Button {
+        custom_id: Some(style.to_custom_id()),
+        disabled: false,
+        emoji: None,
+        label: Some("Save".to_string()),
+        style: ButtonStyle::Success,
+        url: None,
+    });
+    let cancel_button = Component::Button(Button {
+        custom_id: Some(CANCEL_ID.to_string()),
+        disabled: false,
+        emoji: None,
+        label: Some("Cancel".to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    });
+    let embed = EmbedBuilder::new()
+        .description(format!(
+            "Here's how your card will look. Save it?\n\n{}",
+            shown.describe()
+        ))
+        .color(crate::THEME_COLOR)
+        .build();
+    let attachments = [image];
+    let embeds = [embed];
+    let components = [Component::ActionRow(ActionRow {
+        components: vec![save_button, cancel_button],
+    })];
+    state
+        .client
+        .interaction(state.my_id)
+        .create_followup(token)
+        .attachments(&attachments)?
+        .embeds(&embeds)?
+        .components(&components)?
+        .flags(MessageFlags::EPHEMERAL)
+        .await?;
+    Ok(())
+}
+
+pub async fn process_card_component(
+    data: MessageComponentInteractionData,
+    invoker: Id<UserMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let mut response = InteractionResponseDataBuilder::new().components([]);
+    let description = if data.custom_id == CANCEL_ID {
+        response = response.attachments([]);
+        "Nothing was changed.".to_string()
+    } else {
+        let style =
+            CardStyle::from_custom_id(&data.custom_id).ok_or(Error::InvalidCustomButtonId)?;
+        // The button was made from a checked style, but anyone can send any custom ID.
+        if let Some(problem) = style.contrast_problem() {
+            return Err(problem);
+        }
+        #[allow(clippy::cast_possible_wrap)]
+        query!(
+            "INSERT INTO card_styles (id, background, foreground, progress_bar, text, font)
+             VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO UPDATE SET
+             background = excluded.background, foreground = excluded.foreground,
+             progress_bar = excluded.progress_bar, text = excluded.text, font = excluded.font",
+            invoker.get() as i64,
+            style.background,
+            style.foreground,
+            style.progress_bar,
+            style.text,
+            style.font.map(|font| font.value())
+        )
+        .execute(&state.db)
+        .await?;
+        "Saved your new card!".to_string()
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    // Replace the preview's buttons so they can't be clicked twice
+    Ok(InteractionResponse {
+        kind: InteractionResponseType::UpdateMessage,
+        data: Some(response.embeds([embed]).build()),
+    })
+}
+
+pub async fn process_theme(
+    cmd: ThemeCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let old = state.configs.get(guild_id, &state.db).await?;
+    let description = match cmd {
+        ThemeCommand::Set(set) => {
+            let config = set_theme(set, guild_id, &old, &state).await?;
+            describe_theme(&config)
+        }
+        ThemeCommand::Reset(_) => {
+            #[allow(clippy::cast_possible_wrap)]
+            query!(
+                "DELETE FROM card_themes WHERE guild = $1",
+                guild_id.get() as i64
+            )
+            .execute(&state.db)
+            .await?;
+            state.configs.set(
+                guild_id,
+                GuildConfig {
+                    card_theme: CardStyle::default(),
+                    force_card_theme: false,
+                    ..(*old).clone()
+                },
+            );
+            "Cards in this server are back to the default look.".to_string()
+        }
+        ThemeCommand::View(_) => describe_theme(&old),
+        ThemeCommand::Toys(toys) => set_toys(toys, guild_id, &old, &state).await?,
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+async fn set_theme(
+    options: ThemeSet,
+    guild_id: Id<GuildMarker>,
+    old: &GuildConfig,
+    state: &AppState,
+) -> Result<std::sync::Arc<GuildConfig>, Error> {
+    let theme = old
+        .card_theme
+        .edited(
+            [
+                options.background,
+                options.foreground,
+                options.progress_bar,
+                options.text,
+            ],
+            options.font,
+        )
+        .map_err(Error::InvalidColor)?;
+    if let Some(problem) = theme.contrast_problem() {
+        return Err(problem);
+    }
+    let force = options.force.unwrap_or(old.force_card_theme);
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO card_themes (guild, background, foreground, progress_bar, text, font, force)
+         VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (guild) DO UPDATE SET
+         background = excluded.background, foreground = excluded.foreground,
+         progress_bar = excluded.progress_bar, text = excluded.text, font = excluded.font,
+         force = excluded.force",
+        guild_id.get() as i64,
+        theme.background,
+        theme.foreground,
+        theme.progress_bar,
+        theme.text,
+        theme.font.map(|font| font.value()),
+        force
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(state.configs.set(
+        guild_id,
+        GuildConfig {
+            card_theme: theme,
+            force_card_theme: force,
+            ..old.clone()
+        },
+    ))
+}
+
+async fn set_toys(
+    cmd: ThemeToysCommand,
+    guild_id: Id<GuildMarker>,
+    old: &GuildConfig,
+    state: &AppState,
+) -> Result<String, Error> {
+    let mut allowed_toys = old.allowed_toys.clone();
+    #[allow(clippy::cast_possible_wrap)]
+    let description = match cmd {
+        ThemeToysCommand::Add(add) => {
+            let toy = CatalogToy::find(&add.toy, &state.db)
+                .await?
+                .ok_or(Error::UnknownToy(add.toy))?;
+            query!(
+                "INSERT INTO allowed_toys (guild, toy) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+                guild_id.get() as i64,
+                toy.name
+            )
+            .execute(&state.db)
+            .await?;
+            let description = format!("Members can use {} in this server.", toy.name);
+            allowed_toys.insert(toy.name);
+            description
+        }
+        ThemeToysCommand::Remove(remove) => {
+            // Toys can be dropped from the catalog while still being allowed here, so
+            // this goes by what's stored instead of looking the toy up.
+            let removed = query!(
+                "DELETE FROM allowed_toys WHERE guild = $1 AND lower(toy) = lower($2) RETURNING toy",
+                guild_id.get() as i64,
+                remove.toy.trim()
+            )
+            .fetch_optional(&state.db)
+            .await?
+            .ok_or(Error::UnknownToy(remove.toy))?
+            .toy;
+            allowed_toys.remove(&removed);
+            if allowed_toys.is_empty() {
+                "Members can use any toy in this server again.".to_string()
+            } else {
+                format!("Members can no longer use {removed} in this server.")
+            }
+        }
+        ThemeToysCommand::Clear(_) => {
+            query!(
+                "DELETE FROM allowed_toys WHERE guild = $1",
+                guild_id.get() as i64
+            )
+            .execute(&state.db)
+            .await?;
+            allowed_toys.clear();
+            "Members can use any toy in this server again.".to_string()
+        }
+    };
+    state.configs.set(
+        guild_id,
+        GuildConfig {
+            allowed_toys,
+            ..old.clone()
+        },
+    );
+    Ok(description)
+}
+
+fn describe_theme(config: &GuildConfig) -> String {
+    let toys = if config.allowed_toys.is_empty() {
+        "Any".to_string()
+    } else {
+        let mut toys: Vec<&str> = config.allowed_toys.iter().map(String::as_str).collect();
+        toys.sort_unstable();
+        toys.join(", ")
+    };
+    let forced = if config.force_card_theme {
+        "Everyone uses this theme, whatever they picked themselves."
+    } else {
+        "Members' own choices take priority over this theme."
+    };
+    format!(
+        "{}\n**Allowed toys:** {toys}\n\n{forced}",
+        config.card_theme.describe()
+    )
+}
+
+/// Turns `#abc123` or `abc123` into `#ABC123`.
+fn parse_color(input: &str) -> Option<String> {
+    let hex = input.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(format!("#{}", hex.to_ascii_uppercase()))
+}
+
+// https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio
+fn contrast(a: Color, b: Color) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn luminance(color: Color) -> f64 {
+    // Color doesn't expose its channels, but it always prints as #RRGGBB.
+    let hex = color.to_string();
+    let channel = |range: std::ops::Range<usize>| {
+        let value = f64::from(u8::from_str_radix(&hex[range], 16).unwrap_or(0)) / 255.0;
+        if value <= 0.039_28 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126f64.mul_add(
+        channel(1..3),
+        0.7152f64.mul_add(channel(3..5), 0.0722 * channel(5..7)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(hex: &str) -> Color {
+        Color::from_hex(&hex).unwrap()
+    }
+
+    #[test]
+    fn colors_are_normalized() {
+        assert_eq!(parse_color("abc123").as_deref(), Some("#ABC123"));
+        assert_eq!(parse_color(" #AbC123 ").as_deref(), Some("#ABC123"));
+        assert_eq!(parse_color("#abc"), None);
+        assert_eq!(parse_color("#abc1234"), None);
+        assert_eq!(parse_color("#ghijkl"), None);
+        assert_eq!(parse_color(""), None);
+    }
+
+    #[test]
+    fn contrast_matches_wcag() {
+        let black = color("#000000");
+        let white = color("#FFFFFF");
+        assert!((contrast(black, white) - 21.0).abs() < 0.01);
+        assert!((contrast(white, black) - 21.0).abs() < 0.01);
+        assert!((contrast(white, white) - 1.0).abs() < f64::EPSILON);
+        // Mid grey on white is the usual just-too-light example
+        assert!(contrast(color("#777777"), white) < MIN_TEXT_CONTRAST);
+        assert!(contrast(color("#767676"), white) >= MIN_TEXT_CONTRAST);
+    }
+
+    #[test]
+    fn default_card_is_readable() {
+        assert!(CardStyle::default().contrast_problem().is_none());
+    }
+
+    #[test]
+    fn unreadable_cards_are_caught() {
+        let text = CardStyle {
+            background: Some("#FFFFFF".to_string()),
+            text: Some("#EEEEEE".to_string()),
+            ..CardStyle::default()
+        };
+        assert!(matches!(
+            text.contrast_problem(),
+            Some(Error::LowContrast(_, _, _, minimum)) if minimum == MIN_TEXT_CONTRAST
+        ));
+        let progress = CardStyle {
+            foreground: Some("#CCEEAA".to_string()),
+            progress_bar: Some("#CCEEAA".to_string()),
+            ..CardStyle::default()
+        };
+        assert!(matches!(
+            progress.contrast_problem(),
+            Some(Error::LowContrast(_, _, _, minimum)) if minimum == MIN_PROGRESS_CONTRAST
+        ));
+        let level = CardStyle {
+            background: Some("#8FCA5C".to_string()),
+            text: Some("#000000".to_string()),
+            ..CardStyle::default()
+        };
+        assert!(matches!(
+            level.contrast_problem(),
+            Some(Error::LowContrast(front, _, _, _)) if front == "#8FCA5C"
+        ));
+    }
+
+    #[test]
+    fn styles_round_trip_through_buttons() {
+        let style = CardStyle {
+            background: Some("#112233".to_string()),
+            foreground: None,
+            progress_bar: Some("#445566".to_string()),
+            text: Some("#FFFFFF".to_string()),
+            font: Some(CardFont::JetBrainsMono),
+        };
+        let custom_id = style.to_custom_id();
+        assert_eq!(
+            custom_id,
+            "card_save_#112233..#445566.#FFFFFF.jetbrains_mono"
+        );
+        assert!(custom_id.len() <= 100);
+        assert_eq!(CardStyle::from_custom_id(&custom_id), Some(style));
+        let default = CardStyle::default();
+        assert_eq!(
+            CardStyle::from_custom_id(&default.to_custom_id()),
+            Some(default)
+        );
+    }
+
+    #[test]
+    fn bad_save_buttons_are_rejected() {
+        assert_eq!(CardStyle::from_custom_id("card_cancel"), None);
+        assert_eq!(CardStyle::from_custom_id("card_save_#112233.."), None);
+    }
+
+    #[test]
+    fn edits_keep_what_was_not_picked() {
+        let style = CardStyle {
+            background: Some("#112233".to_string()),
+            ..CardStyle::default()
+        };
+        let edited = style
+            .edited([None, None, Some("abcdef".to_string()), None], None)
+            .unwrap();
+        assert_eq!(edited.background.as_deref(), Some("#112233"));
+        assert_eq!(edited.progress_bar.as_deref(), Some("#ABCDEF"));
+        assert_eq!(
+            style.edited([Some("nope".to_string()), None, None, None], None),
+            Err("nope".to_string())
+        );
+    }
+
+    #[test]
+    fn unreadable_mixes_leave_the_theme_out() {
+        let theme = CardStyle {
+            background: Some("#FFFFFF".to_string()),
+            text: Some("#000000".to_string()),
+            progress_bar: Some("#000000".to_string()),
+            ..CardStyle::default()
+        };
+        let config = GuildConfig {
+            card_theme: theme,
+            ..GuildConfig::default()
+        };
+        // white text is fine on the default background, but not on the theme's
+        let mine = CardStyle {
+            text: Some("#FFFFFF".to_string()),
+            ..CardStyle::default()
+        };
+        assert_eq!(mine.themed(&config), mine);
+        // picking only a font still gets the rest of the theme
+        let font_only = CardStyle {
+            font: Some(CardFont::Roboto),
+            ..CardStyle::default()
+        };
+        assert_eq!(
+            font_only.themed(&config).background.as_deref(),
+            Some("#FFFFFF")
+        );
+    }
+}
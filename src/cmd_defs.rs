@@ -1,4 +1,13 @@
-use twilight_model::application::command::CommandType;
+use twilight_model::{
+    application::command::CommandType,
+    channel::Attachment,
+    guild::Permissions,
+    id::{
+        marker::{ChannelMarker, RoleMarker},
+        Id,
+    },
+    user::User,
+};
 use twilight_util::builder::command::CommandBuilder;
 
 use twilight_interactions::command::{CommandModel, CreateCommand, ResolvedUser};
@@ -10,6 +19,14 @@ pub struct LeaderboardCommand {
     pub user: Option<ResolvedUser>,
     #[command(desc = "Page to jump to", min_value = 1)]
     pub page: Option<i64>,
+    #[command(desc = "Rank by XP earned in this time window")]
+    pub period: Option<crate::leaderboard::Period>,
+    #[command(desc = "Show the final standings of a past season", max_length = 64)]
+    pub season: Option<String>,
+    #[command(desc = "Show the leaderboard as a picture, with avatars and progress bars")]
+    pub image: Option<bool>,
+    #[command(desc = "Only rank members with this role")]
+    pub role: Option<Id<RoleMarker>>,
 }
 
 #[derive(CommandModel, CreateCommand)]
@@ -21,20 +38,540 @@ pub struct LeaderboardCommand {
 pub struct RankCommand {
     #[command(desc = "User to check level of")]
     pub user: Option<ResolvedUser>,
+    #[command(desc = "Show a chart of XP over time instead of a rank card")]
+    pub history: Option<crate::history::HistoryRange>,
 }
 
 #[derive(CommandModel, CreateCommand)]
 #[command(name = "toy", desc = "Pick a toy image to use in your card")]
 pub struct ToyCommand {
-    #[command(desc = "What toy image to use in the card")]
-    pub toy_image: crate::toy::Toy,
+    #[command(
+        desc = "What toy image to use in the card, or None",
+        autocomplete = true,
+        max_length = 64
+    )]
+    pub toy_image: String,
+    #[command(
+        desc = "Whether to use it in just this server, or everywhere (defaults to this server)"
+    )]
+    pub scope: Option<crate::toy::ToyScope>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "card", desc = "Change how your rank card looks")]
+pub enum CardCommand {
+    #[command(name = "edit")]
+    Edit(CardEdit),
+    #[command(name = "reset")]
+    Reset(CardReset),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "edit",
+    desc = "Preview new colors or a new font for your card, then save them"
+)]
+pub struct CardEdit {
+    #[command(desc = "Background color, as hex like #1E1F22", max_length = 7)]
+    pub background: Option<String>,
+    #[command(
+        desc = "Border and empty progress bar color, as hex like #1E1F22",
+        max_length = 7
+    )]
+    pub foreground: Option<String>,
+    #[command(
+        desc = "Progress bar and level color, as hex like #1E1F22",
+        max_length = 7
+    )]
+    pub progress_bar: Option<String>,
+    #[command(desc = "Text color, as hex like #1E1F22", max_length = 7)]
+    pub text: Option<String>,
+    #[command(desc = "Font for the text on your card")]
+    pub font: Option<crate::card::CardFont>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "reset", desc = "Go back to the default card colors and font")]
+pub struct CardReset;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "theme",
+    desc = "Set how rank cards look in this server",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub enum ThemeCommand {
+    #[command(name = "set")]
+    Set(ThemeSet),
+    #[command(name = "reset")]
+    Reset(ThemeReset),
+    #[command(name = "view")]
+    View(ThemeView),
+    #[command(name = "toys")]
+    Toys(ThemeToysCommand),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "set",
+    desc = "Pick the colors and font cards use when members haven't picked their own"
+)]
+pub struct ThemeSet {
+    #[command(desc = "Background color, as hex like #1E1F22", max_length = 7)]
+    pub background: Option<String>,
+    #[command(
+        desc = "Border and empty progress bar color, as hex like #1E1F22",
+        max_length = 7
+    )]
+    pub foreground: Option<String>,
+    #[command(
+        desc = "Progress bar and level color, as hex like #1E1F22",
+        max_length = 7
+    )]
+    pub progress_bar: Option<String>,
+    #[command(desc = "Text color, as hex like #1E1F22", max_length = 7)]
+    pub text: Option<String>,
+    #[command(desc = "Font for the text on cards")]
+    pub font: Option<crate::card::CardFont>,
+    #[command(desc = "Use this theme even for members who picked their own")]
+    pub force: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "reset", desc = "Go back to the default card theme")]
+pub struct ThemeReset;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "view", desc = "Show this server's card theme")]
+pub struct ThemeView;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "toys",
+    desc = "Choose which toys members can put on their cards"
+)]
+pub enum ThemeToysCommand {
+    #[command(name = "add")]
+    Add(ThemeToysAdd),
+    #[command(name = "remove")]
+    Remove(ThemeToysRemove),
+    #[command(name = "clear")]
+    Clear(ThemeToysClear),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add",
+    desc = "Allow a toy. Once any are allowed, members can only use those"
+)]
+pub struct ThemeToysAdd {
+    #[command(desc = "Toy to allow", autocomplete = true, max_length = 64)]
+    pub toy: String,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "remove", desc = "Stop allowing a toy")]
+pub struct ThemeToysRemove {
+    #[command(desc = "Toy to stop allowing", autocomplete = true, max_length = 64)]
+    pub toy: String,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "clear", desc = "Let members use any toy again")]
+pub struct ThemeToysClear;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "rewards",
+    desc = "Manage the roles granted for reaching a level",
+    dm_permission = false,
+    default_permissions = "manage_roles"
+)]
+pub enum RewardsCommand {
+    #[command(name = "add")]
+    Add(RewardsAdd),
+    #[command(name = "remove")]
+    Remove(RewardsRemove),
+    #[command(name = "list")]
+    List(RewardsList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "add", desc = "Grant a role when users reach a level")]
+pub struct RewardsAdd {
+    #[command(desc = "What level to grant the role at", min_value = 1)]
+    pub level: i64,
+    #[command(desc = "What role to grant")]
+    pub role: Id<RoleMarker>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "remove", desc = "Stop granting a role reward")]
+pub struct RewardsRemove {
+    #[command(desc = "Which level to remove the reward for", min_value = 1)]
+    pub level: Option<i64>,
+    #[command(desc = "Which role to stop granting")]
+    pub role: Option<Id<RoleMarker>>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "list", desc = "Show all role rewards in this server")]
+pub struct RewardsList;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "xp",
+    desc = "Change the XP of users in this server",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub enum XpCommand {
+    #[command(name = "give")]
+    Give(XpGive),
+    #[command(name = "take")]
+    Take(XpTake),
+    #[command(name = "set")]
+    Set(XpSet),
+    #[command(name = "reset")]
+    Reset(XpReset),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "give", desc = "Give XP to a user")]
+pub struct XpGive {
+    #[command(desc = "Who to give XP to")]
+    pub user: User,
+    #[command(desc = "How much XP to give", min_value = 1, max_value = 1_000_000_000)]
+    pub amount: i64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "take", desc = "Take XP away from a user")]
+pub struct XpTake {
+    #[command(desc = "Who to take XP from")]
+    pub user: User,
+    #[command(desc = "How much XP to take", min_value = 1, max_value = 1_000_000_000)]
+    pub amount: i64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "set", desc = "Set a user's XP to an exact amount")]
+pub struct XpSet {
+    #[command(desc = "Whose XP to set")]
+    pub user: User,
+    #[command(desc = "How much XP they should have", min_value = 0)]
+    pub amount: i64,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "reset",
+    desc = "Delete XP for one user, or everyone in this server"
+)]
+pub struct XpReset {
+    #[command(desc = "Who to reset. Leave empty to reset everyone")]
+    pub user: Option<User>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "config",
+    desc = "Change how leveling works in this server",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub enum ConfigCommand {
+    #[command(name = "levels")]
+    Levels(ConfigLevels),
+    #[command(name = "levelup")]
+    LevelUp(ConfigLevelUp),
+    #[command(name = "public")]
+    Public(ConfigPublic),
+    #[command(name = "view")]
+    View(ConfigView),
+    #[command(name = "reset")]
+    Reset(ConfigReset),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "levels", desc = "Change how much XP messages are worth")]
+pub struct ConfigLevels {
+    #[command(
+        desc = "Seconds users must wait between messages that earn XP",
+        min_value = 0,
+        max_value = 86400
+    )]
+    pub cooldown: Option<i64>,
+    #[command(desc = "Least XP a message can earn", min_value = 0, max_value = 1000)]
+    pub min_xp: Option<i64>,
+    #[command(desc = "Most XP a message can earn", min_value = 0, max_value = 1000)]
+    pub max_xp: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "levelup", desc = "Change how level-ups are announced")]
+pub struct ConfigLevelUp {
+    #[command(desc = "Where to announce level-ups")]
+    pub mode: crate::config::LevelUpMode,
+    #[command(
+        desc = "Channel to announce level-ups in, for the channel mode",
+        channel_types = "guild_text guild_announcement"
+    )]
+    pub channel: Option<Id<ChannelMarker>>,
+    #[command(
+        desc = "Announcement text. Supports {user}, {level}, {rank} and {xp}",
+        max_length = 1000
+    )]
+    pub message: Option<String>,
+    #[command(desc = "Forget the saved channel and message, unless new ones are given here")]
+    pub reset: Option<bool>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "public",
+    desc = "Choose whether anyone can see this server's leaderboard on the web"
+)]
+pub struct ConfigPublic {
+    #[command(desc = "Whether the leaderboard is public")]
+    pub enabled: bool,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "view", desc = "Show the current settings for this server")]
+pub struct ConfigView;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "reset", desc = "Put all settings back to their defaults")]
+pub struct ConfigReset;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "exclusions",
+    desc = "Stop channels or roles from earning XP",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub enum ExclusionsCommand {
+    #[command(name = "add")]
+    Add(ExclusionsAdd),
+    #[command(name = "remove")]
+    Remove(ExclusionsRemove),
+    #[command(name = "list")]
+    List(ExclusionsList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "add",
+    desc = "Stop a channel, category or role from earning XP"
+)]
+pub struct ExclusionsAdd {
+    #[command(desc = "Channel or category where messages earn no XP")]
+    pub channel: Option<Id<ChannelMarker>>,
+    #[command(desc = "Role whose members earn no XP")]
+    pub role: Option<Id<RoleMarker>>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Let a channel, category or role earn XP again"
+)]
+pub struct ExclusionsRemove {
+    #[command(desc = "Channel or category to remove from the exclusions")]
+    pub channel: Option<Id<ChannelMarker>>,
+    #[command(desc = "Role to remove from the exclusions")]
+    pub role: Option<Id<RoleMarker>>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "list",
+    desc = "Show everything that can't earn XP in this server"
+)]
+pub struct ExclusionsList;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "multipliers",
+    desc = "Make channels or roles earn more or less XP",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub enum MultipliersCommand {
+    #[command(name = "set")]
+    Set(MultipliersSet),
+    #[command(name = "remove")]
+    Remove(MultipliersRemove),
+    #[command(name = "list")]
+    List(MultipliersList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "set",
+    desc = "Set the XP multiplier for a channel, category or role"
+)]
+pub struct MultipliersSet {
+    #[command(
+        desc = "How much XP is multiplied by, like 1.5 or 0.5",
+        min_value = 0.0,
+        max_value = 10.0
+    )]
+    pub multiplier: f64,
+    #[command(desc = "Channel or category to set the multiplier for")]
+    pub channel: Option<Id<ChannelMarker>>,
+    #[command(desc = "Role to set the multiplier for")]
+    pub role: Option<Id<RoleMarker>>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "remove",
+    desc = "Remove the XP multiplier from a channel, category or role"
+)]
+pub struct MultipliersRemove {
+    #[command(desc = "Channel or category to remove the multiplier from")]
+    pub channel: Option<Id<ChannelMarker>>,
+    #[command(desc = "Role to remove the multiplier from")]
+    pub role: Option<Id<RoleMarker>>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "list", desc = "Show every XP multiplier in this server")]
+pub struct MultipliersList;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "import",
+    desc = "Bring in levels from another bot",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+// This is only ever built once per command, so the size difference doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum ImportCommand {
+    #[command(name = "mee6")]
+    Mee6(ImportMee6),
+    #[command(name = "file")]
+    File(ImportFile),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "mee6",
+    desc = "Copy XP from this server's public MEE6 leaderboard, replacing what is here"
+)]
+pub struct ImportMee6;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "file",
+    desc = "Load XP from a CSV or JSON file, like the ones /export makes"
+)]
+pub struct ImportFile {
+    #[command(desc = "File with an id and xp for each user")]
+    pub file: Attachment,
+    #[command(desc = "Whether to replace existing XP or add to it")]
+    pub mode: crate::import::ImportMode,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "export",
+    desc = "Download everyone's levels in this server",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub struct ExportCommand {
+    #[command(desc = "What kind of file to download")]
+    pub format: crate::export::ExportFormat,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "season",
+    desc = "Archive the leaderboard and start over",
+    dm_permission = false,
+    default_permissions = "manage_guild"
+)]
+pub enum SeasonCommand {
+    #[command(name = "end")]
+    End(SeasonEnd),
+    #[command(name = "schedule")]
+    Schedule(SeasonSchedule),
+    #[command(name = "unschedule")]
+    Unschedule(SeasonUnschedule),
+    #[command(name = "list")]
+    List(SeasonList),
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(
+    name = "end",
+    desc = "End the current season now, saving its leaderboard under a name"
+)]
+pub struct SeasonEnd {
+    #[command(desc = "What to call the season that is ending", max_length = 64)]
+    pub name: String,
+    #[command(
+        desc = "Percent of their XP everyone keeps for the next season (default 0)",
+        min_value = 0,
+        max_value = 100
+    )]
+    pub carry_over: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "schedule", desc = "End seasons automatically every few days")]
+pub struct SeasonSchedule {
+    #[command(
+        desc = "How many days each season lasts",
+        min_value = 1,
+        max_value = 3650
+    )]
+    pub every_days: i64,
+    #[command(
+        desc = "Percent of their XP everyone keeps for the next season (default 0)",
+        min_value = 0,
+        max_value = 100
+    )]
+    pub carry_over: Option<i64>,
+}
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "unschedule", desc = "Stop ending seasons automatically")]
+pub struct SeasonUnschedule;
+
+#[derive(CommandModel, CreateCommand)]
+#[command(name = "list", desc = "Show past seasons and the schedule")]
+pub struct SeasonList;
+
+const fn manage_roles() -> Permissions {
+    Permissions::MANAGE_ROLES
+}
+
+const fn manage_guild() -> Permissions {
+    Permissions::MANAGE_GUILD
 }
 
 pub async fn register(http: twilight_http::client::InteractionClient<'_>) {
     let cmds = vec![
         RankCommand::create_command().into(),
         ToyCommand::create_command().into(),
+        CardCommand::create_command().into(),
+        ThemeCommand::create_command().into(),
         LeaderboardCommand::create_command().into(),
+        RewardsCommand::create_command().into(),
+        XpCommand::create_command().into(),
+        ConfigCommand::create_command().into(),
+        ExclusionsCommand::create_command().into(),
+        MultipliersCommand::create_command().into(),
+        ImportCommand::create_command().into(),
+        ExportCommand::create_command().into(),
+        SeasonCommand::create_command().into(),
         CommandBuilder::new("Get level", "", CommandType::User).build(),
         CommandBuilder::new("Get author level", "", CommandType::Message).build(),
     ];
@@ -0,0 +1,443 @@
+use std::{sync::Arc, time::Duration};
+
+use ahash::{AHashMap, AHashSet};
+use parking_lot::RwLock;
+use twilight_interactions::command::{CommandOption, CreateOption};
+use twilight_model::{
+    http::interaction::InteractionResponse,
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+
+use crate::{
+    card::{CardFont, CardStyle},
+    cmd_defs::{ConfigCommand, ConfigLevelUp, ConfigLevels},
+    ephemeral_embed_response,
+    metrics::Metrics,
+    AppState, Error,
+};
+
+/// Used when a guild turns on level-up announcements without picking its own message.
+pub const DEFAULT_LEVEL_UP_MESSAGE: &str = "Congratulations {user}, you reached level {level}!";
+
+#[derive(Clone, Debug)]
+pub struct GuildConfig {
+    pub cooldown: Duration,
+    pub min_xp: i64,
+    pub max_xp: i64,
+    pub level_up_mode: LevelUpMode,
+    pub level_up_channel: Option<Id<ChannelMarker>>,
+    pub level_up_message: Option<String>,
+    pub excluded_channels: AHashSet<Id<ChannelMarker>>,
+    pub excluded_roles: AHashSet<Id<RoleMarker>>,
+    pub role_multipliers: AHashMap<Id<RoleMarker>, f64>,
+    pub channel_multipliers: AHashMap<Id<ChannelMarker>, f64>,
+    pub card_theme: CardStyle,
+    pub force_card_theme: bool,
+    /// Empty means every toy is allowed.
+    pub allowed_toys: AHashSet<String>,
+    /// Whether the leaderboard is on the web for anyone to see.
+    pub public_leaderboard: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, CreateOption, CommandOption)]
+pub enum LevelUpMode {
+    #[option(name = "Off", value = "off")]
+    Off,
+    #[option(name = "Channel the message was sent in", value = "current")]
+    Current,
+    #[option(name = "A specific channel", value = "channel")]
+    Channel,
+    #[option(name = "Direct message", value = "dm")]
+    Dm,
+}
+
+impl LevelUpMode {
+    // Anything we don't recognize is treated as off, so a bad row can't make us spam.
+    fn from_value(value: &str) -> Self {
+        match value {
+            "current" => Self::Current,
+            "channel" => Self::Channel,
+            "dm" => Self::Dm,
+            _ => Self::Off,
+        }
+    }
+}
+
+impl std::fmt::Display for LevelUpMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            Self::Off => "Off",
+            Self::Current => "Channel the message was sent in",
+            Self::Channel => "A specific channel",
+            Self::Dm => "Direct message",
+        };
+        f.write_str(text)
+    }
+}
+
+// These must match the column defaults in the guild_config migration.
+impl Default for GuildConfig {
+    fn default() -> Self {
+        Self {
+            cooldown: Duration::from_secs(60),
+            min_xp: 15,
+            max_xp: 25,
+            level_up_mode: LevelUpMode::Off,
+            level_up_channel: None,
+            level_up_message: None,
+            excluded_channels: AHashSet::new(),
+            excluded_roles: AHashSet::new(),
+            role_multipliers: AHashMap::new(),
+            channel_multipliers: AHashMap::new(),
+            card_theme: CardStyle::default(),
+            force_card_theme: false,
+            allowed_toys: AHashSet::new(),
+            public_leaderboard: false,
+        }
+    }
+}
+
+// Triggers on every table a GuildConfig is built from send the guild ID here when it changes.
+const CHANGE_CHANNEL: &str = "config_changed";
+// How long to wait before listening again, if the database connection goes away
+const RELISTEN_DELAY: Duration = Duration::from_secs(5);
+
+/// Keeps every guild's config in memory, so saving a message doesn't need another query.
+/// Guilds are loaded from the database the first time they are asked for, and forgotten
+/// when any process changes them, see [`invalidation_task`].
+#[derive(Clone)]
+pub struct ConfigCache {
+    inner: Arc<RwLock<CachedConfigs>>,
+    metrics: Metrics,
+}
+
+#[derive(Debug, Default)]
+struct CachedConfigs {
+    guilds: AHashMap<Id<GuildMarker>, Arc<GuildConfig>>,
+    /// Goes up every time something is forgotten, so a load that started before
+    /// then knows not to cache what it found.
+    generation: u64,
+}
+
+impl ConfigCache {
+    pub fn new(metrics: Metrics) -> Self {
+        Self {
+            inner: Arc::default(),
+            metrics,
+        }
+    }
+    pub async fn get(
+        &self,
+        guild_id: Id<GuildMarker>,
+        db: &sqlx::PgPool,
+    ) -> Result<Arc<GuildConfig>, Error> {
+        // the read lock has to be dropped before we await, so this is its own statement
+        let (cached, generation) = {
+            let cache = self.inner.read();
+            (cache.guilds.get(&guild_id).cloned(), cache.generation)
+        };
+        if let Some(config) = cached {
+            return Ok(config);
+        }
+        let config = Arc::new(self.metrics.timed("config", load(guild_id, db)).await?);
+        // If anything was forgotten while we were loading, what we loaded might be out of date.
+        // It's still the best we have for this lookup, but the next one will load it again.
+        let mut cache = self.inner.write();
+        if cache.generation == generation {
+            cache.guilds.insert(guild_id, config.clone());
+        }
+        drop(cache);
+        Ok(config)
+    }
+    pub fn set(&self, guild_id: Id<GuildMarker>, config: GuildConfig) -> Arc<GuildConfig> {
+        let config = Arc::new(config);
+        self.inner.write().guilds.insert(guild_id, config.clone());
+        config
+    }
+    /// Drops a guild's config, so the next lookup loads it from the database again.
+    pub fn forget(&self, guild_id: Id<GuildMarker>) {
+        let mut cache = self.inner.write();
+        cache.guilds.remove(&guild_id);
+        cache.generation += 1;
+    }
+    fn clear(&self) {
+        let mut cache = self.inner.write();
+        cache.guilds.clear();
+        cache.generation += 1;
+    }
+}
+
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+async fn load(guild_id: Id<GuildMarker>, db: &sqlx::PgPool) -> Result<GuildConfig, Error> {
+    let guild = guild_id.get() as i64;
+    let (
+        base,
+        excluded_channels,
+        excluded_roles,
+        role_multipliers,
+        channel_multipliers,
+        theme,
+        allowed_toys,
+    ) = tokio::try_join!(
+        query!(
+            "SELECT cooldown, min_xp, max_xp, level_up_mode, level_up_channel, level_up_message,
+             public_leaderboard FROM guild_config WHERE id = $1",
+            guild
+        )
+        .fetch_optional(db),
+        query!("SELECT id FROM excluded_channels WHERE guild = $1", guild).fetch_all(db),
+        query!("SELECT id FROM excluded_roles WHERE guild = $1", guild).fetch_all(db),
+        query!(
+            "SELECT id, multiplier FROM role_multipliers WHERE guild = $1",
+            guild
+        )
+        .fetch_all(db),
+        query!(
+            "SELECT id, multiplier FROM channel_multipliers WHERE guild = $1",
+            guild
+        )
+        .fetch_all(db),
+        query!(
+            "SELECT background, foreground, progress_bar, text, font, force
+             FROM card_themes WHERE guild = $1",
+            guild
+        )
+        .fetch_optional(db),
+        query!("SELECT toy FROM allowed_toys WHERE guild = $1", guild).fetch_all(db),
+    )?;
+    let mut config = base.map_or_else(GuildConfig::default, |v| GuildConfig {
+        cooldown: Duration::from_secs(v.cooldown as u64),
+        min_xp: v.min_xp,
+        max_xp: v.max_xp,
+        level_up_mode: LevelUpMode::from_value(&v.level_up_mode),
+        level_up_channel: v.level_up_channel.and_then(|v| Id::new_checked(v as u64)),
+        level_up_message: v.level_up_message,
+        public_leaderboard: v.public_leaderboard,
+        ..GuildConfig::default()
+    });
+    config.excluded_channels = excluded_channels
+        .into_iter()
+        .map(|v| Id::new(v.id as u64))
+        .collect();
+    config.excluded_roles = excluded_roles
+        .into_iter()
+        .map(|v| Id::new(v.id as u64))
+        .collect();
+    config.role_multipliers = role_multipliers
+        .into_iter()
+        .map(|v| (Id::new(v.id as u64), v.multiplier))
+        .collect();
+    config.channel_multipliers = channel_multipliers
+        .into_iter()
+        .map(|v| (Id::new(v.id as u64), v.multiplier))
+        .collect();
+    if let Some(theme) = theme {
+        config.card_theme = CardStyle {
+            background: theme.background,
+            foreground: theme.foreground,
+            progress_bar: theme.progress_bar,
+            text: theme.text,
+            font: theme.font.as_deref().and_then(CardFont::from_value),
+        };
+        config.force_card_theme = theme.force;
+    }
+    config.allowed_toys = allowed_toys.into_iter().map(|v| v.toy).collect();
+    Ok(config)
+}
+
+/// Forgets cached configs whenever they change in the database, forever. This is how
+/// processes sharing a database see each other's `/config`, `/exclusions`, `/multipliers`
+/// and `/theme` changes.
+pub async fn invalidation_task(db: sqlx::PgPool, configs: ConfigCache) {
+    loop {
+        if let Err(e) = listen_for_changes(&db, &configs).await {
+            warn!("Failed to listen for config changes: {e}");
+        }
+        tokio::time::sleep(RELISTEN_DELAY).await;
+    }
+}
+
+async fn listen_for_changes(db: &sqlx::PgPool, configs: &ConfigCache) -> Result<(), Error> {
+    let mut listener = sqlx::postgres::PgListener::connect_with(db).await?;
+    listener.listen(CHANGE_CHANNEL).await?;
+    // Anything could have changed while we weren't listening.
+    configs.clear();
+    // This gives None when the connection drops, and then we start over.
+    while let Some(notification) = listener.try_recv().await? {
+        let guild_id = notification
+            .payload()
+            .parse()
+            .ok()
+            .and_then(Id::new_checked);
+        if let Some(guild_id) = guild_id {
+            configs.forget(guild_id);
+        } else {
+            warn!(
+                "Got a config change for bad guild ID {}",
+                notification.payload()
+            );
+        }
+    }
+    warn!("Lost the connection listening for config changes");
+    Ok(())
+}
+
+pub async fn process_config(
+    cmd: ConfigCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let config = match cmd {
+        ConfigCommand::Levels(levels) => set_levels(levels, guild_id, &state).await?,
+        ConfigCommand::LevelUp(level_up) => set_level_up(level_up, guild_id, &state).await?,
+        ConfigCommand::Public(public) => set_public(public.enabled, guild_id, &state).await?,
+        ConfigCommand::View(_) => state.configs.get(guild_id, &state.db).await?,
+        ConfigCommand::Reset(_) => {
+            #[allow(clippy::cast_possible_wrap)]
+            query!(
+                "DELETE FROM guild_config WHERE id = $1",
+                guild_id.get() as i64
+            )
+            .execute(&state.db)
+            .await?;
+            let old = state.configs.get(guild_id, &state.db).await?;
+            // exclusions, multipliers and the card theme have their own commands,
+            // so they survive a reset
+            let config = GuildConfig {
+                excluded_channels: old.excluded_channels.clone(),
+                excluded_roles: old.excluded_roles.clone(),
+                role_multipliers: old.role_multipliers.clone(),
+                channel_multipliers: old.channel_multipliers.clone(),
+                card_theme: old.card_theme.clone(),
+                force_card_theme: old.force_card_theme,
+                allowed_toys: old.allowed_toys.clone(),
+                ..GuildConfig::default()
+            };
+            state.configs.set(guild_id, config)
+        }
+    };
+    let level_up_channel = config
+        .level_up_channel
+        .map_or_else(|| "None".to_string(), |id| format!("<#{id}>"));
+    let embed = EmbedBuilder::new()
+        .description(format!(
+            "**Cooldown:** {} seconds\n**XP per message:** {} to {}\n\
+             **Level-up announcements:** {}\n**Level-up channel:** {level_up_channel}\n\
+             **Level-up message:** {}\n**Public leaderboard:** {}",
+            config.cooldown.as_secs(),
+            config.min_xp,
+            config.max_xp,
+            config.level_up_mode,
+            config
+                .level_up_message
+                .as_deref()
+                .unwrap_or(DEFAULT_LEVEL_UP_MESSAGE),
+            if config.public_leaderboard {
+                "On"
+            } else {
+                "Off"
+            }
+        ))
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+async fn set_levels(
+    options: ConfigLevels,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<Arc<GuildConfig>, Error> {
+    let old = state.configs.get(guild_id, &state.db).await?;
+    #[allow(clippy::cast_sign_loss)]
+    let config = GuildConfig {
+        cooldown: options
+            .cooldown
+            .map_or(old.cooldown, |v| Duration::from_secs(v as u64)),
+        min_xp: options.min_xp.unwrap_or(old.min_xp),
+        max_xp: options.max_xp.unwrap_or(old.max_xp),
+        ..(*old).clone()
+    };
+    if config.min_xp > config.max_xp {
+        return Err(Error::InvertedXpRange);
+    }
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO guild_config (id, cooldown, min_xp, max_xp) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (id) DO UPDATE SET
+         cooldown = excluded.cooldown, min_xp = excluded.min_xp, max_xp = excluded.max_xp",
+        guild_id.get() as i64,
+        config.cooldown.as_secs() as i64,
+        config.min_xp,
+        config.max_xp
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(state.configs.set(guild_id, config))
+}
+
+async fn set_level_up(
+    options: ConfigLevelUp,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<Arc<GuildConfig>, Error> {
+    let old = state.configs.get(guild_id, &state.db).await?;
+    // Resetting means writing NULL, which is the default message and the message's own channel.
+    let (old_channel, old_message) = if options.reset.unwrap_or(false) {
+        (None, None)
+    } else {
+        (old.level_up_channel, old.level_up_message.clone())
+    };
+    let level_up_channel = options.channel.or(old_channel);
+    if options.mode == LevelUpMode::Channel && level_up_channel.is_none() {
+        return Err(Error::NoLevelUpChannel);
+    }
+    let config = GuildConfig {
+        level_up_mode: options.mode,
+        level_up_channel,
+        level_up_message: options.message.or(old_message),
+        ..(*old).clone()
+    };
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO guild_config (id, level_up_mode, level_up_channel, level_up_message)
+         VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO UPDATE SET
+         level_up_mode = excluded.level_up_mode, level_up_channel = excluded.level_up_channel,
+         level_up_message = excluded.level_up_message",
+        guild_id.get() as i64,
+        config.level_up_mode.value(),
+        config.level_up_channel.map(|v| v.get() as i64),
+        config.level_up_message
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(state.configs.set(guild_id, config))
+}
+
+async fn set_public(
+    enabled: bool,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<Arc<GuildConfig>, Error> {
+    let old = state.configs.get(guild_id, &state.db).await?;
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO guild_config (id, public_leaderboard) VALUES ($1, $2)
+         ON CONFLICT (id) DO UPDATE SET public_leaderboard = excluded.public_leaderboard",
+        guild_id.get() as i64,
+        enabled
+    )
+    .execute(&state.db)
+    .await?;
+    Ok(state.configs.set(
+        guild_id,
+        GuildConfig {
+            public_leaderboard: enabled,
+            ..(*old).clone()
+        },
+    ))
+}
@@ -0,0 +1,139 @@
+use std::{sync::Arc, time::Duration};
+
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::{minicache::MessagingCache, Error};
+
+// How often the postgres backend throws away cooldowns that have run out
+const PRUNE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Somewhere to remember who has recently earned XP. Processes that should enforce
+/// the same cooldowns need to share a backend that isn't in memory.
+#[async_trait::async_trait]
+pub trait CooldownBackend: Send + Sync {
+    /// Puts a user on cooldown until `ttl` has passed, unless they already are, and says
+    /// whether it did. Checking and starting are one step, so two messages that arrive
+    /// together can't both earn XP.
+    async fn try_start(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Id<UserMarker>,
+        ttl: Duration,
+    ) -> Result<bool, Error>;
+}
+
+/// Picks a backend from `COOLDOWN_BACKEND`, which is `memory` if it isn't set.
+pub async fn from_env(db: &sqlx::PgPool) -> Arc<dyn CooldownBackend> {
+    let backend = std::env::var("COOLDOWN_BACKEND").unwrap_or_else(|_| "memory".to_string());
+    match backend.as_str() {
+        "memory" => Arc::new(MessagingCache::new()),
+        "postgres" => {
+            tokio::spawn(prune_task(db.clone()));
+            Arc::new(PostgresCooldowns { db: db.clone() })
+        }
+        "redis" => {
+            let url = std::env::var("REDIS_URL")
+                .expect("Expected environment variable REDIS_URL for COOLDOWN_BACKEND=redis");
+            let client = redis::Client::open(url).expect("Failed to parse REDIS_URL");
+            let connection = redis::aio::ConnectionManager::new(client)
+                .await
+                .expect("Failed to connect to redis");
+            Arc::new(RedisCooldowns { connection })
+        }
+        other => panic!("Unknown COOLDOWN_BACKEND {other}, expected memory, postgres or redis"),
+    }
+}
+
+#[async_trait::async_trait]
+impl CooldownBackend for MessagingCache {
+    async fn try_start(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Id<UserMarker>,
+        ttl: Duration,
+    ) -> Result<bool, Error> {
+        Ok(Self::add(self, guild, user, ttl))
+    }
+}
+
+/// Cooldowns kept in an unlogged table, with the time each one runs out.
+pub struct PostgresCooldowns {
+    db: sqlx::PgPool,
+}
+
+#[async_trait::async_trait]
+impl CooldownBackend for PostgresCooldowns {
+    #[allow(clippy::cast_possible_wrap, clippy::cast_precision_loss)]
+    async fn try_start(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Id<UserMarker>,
+        ttl: Duration,
+    ) -> Result<bool, Error> {
+        // A cooldown that has run out gets replaced. One that hasn't makes the update
+        // do nothing, so no row comes back.
+        let started = query!(
+            "INSERT INTO cooldowns (guild, id, expires)
+                VALUES ($1, $2, NOW() + make_interval(secs => $3))
+                ON CONFLICT (guild, id) DO UPDATE SET expires = excluded.expires
+                WHERE cooldowns.expires <= NOW()
+                RETURNING id",
+            guild.get() as i64,
+            user.get() as i64,
+            ttl.as_secs_f64()
+        )
+        .fetch_optional(&self.db)
+        .await?
+        .is_some();
+        Ok(started)
+    }
+}
+
+// Expired rows are ignored anyway, this just keeps the table from growing forever.
+async fn prune_task(db: sqlx::PgPool) {
+    let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(e) = query!("DELETE FROM cooldowns WHERE expires <= NOW()")
+            .execute(&db)
+            .await
+        {
+            warn!("Failed to prune expired cooldowns: {e}");
+        }
+    }
+}
+
+/// Cooldowns kept as keys in redis, or anything that speaks its protocol, which
+/// expire on their own.
+pub struct RedisCooldowns {
+    connection: redis::aio::ConnectionManager,
+}
+
+fn redis_key(guild: Id<GuildMarker>, user: Id<UserMarker>) -> String {
+    format!("minixpd:cooldown:{guild}:{user}")
+}
+
+#[async_trait::async_trait]
+impl CooldownBackend for RedisCooldowns {
+    async fn try_start(
+        &self,
+        guild: Id<GuildMarker>,
+        user: Id<UserMarker>,
+        ttl: Duration,
+    ) -> Result<bool, Error> {
+        // NX leaves an existing cooldown alone, and then the reply is nil instead of OK.
+        #[allow(clippy::cast_possible_truncation)]
+        let reply: Option<String> = redis::cmd("SET")
+            .arg(redis_key(guild, user))
+            .arg(1)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis().max(1) as u64)
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(reply.is_some())
+    }
+}
@@ -3,10 +3,15 @@ use twilight_interactions::command::CommandModel;
 use twilight_model::{
     application::{
         command::CommandType,
-        interaction::{application_command::CommandData, Interaction, InteractionData},
+        interaction::{
+            application_command::CommandData, Interaction, InteractionData, InteractionType,
+        },
     },
     http::interaction::{InteractionResponse, InteractionResponseType},
-    id::{marker::GuildMarker, Id},
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker},
+        Id,
+    },
     user::User,
 };
 
@@ -19,6 +24,13 @@ pub async fn process_interaction(
     interaction: Interaction,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
+    // these are only used to show /rank boosts, so missing them isn't an error
+    let roles = interaction
+        .member
+        .as_ref()
+        .map(|member| member.roles.clone())
+        .unwrap_or_default();
+    let channel_id = interaction.channel.as_ref().map(|channel| channel.id);
     // discord doesn't always send user for some reason. Dumb.
     let invoker = match interaction.member {
         Some(val) => val.user,
@@ -29,15 +41,43 @@ pub async fn process_interaction(
     if let Some(data) = interaction.data {
         let resp = match data {
             // app command == slash command
+            // Autocomplete comes in looking like a command, but only wants suggestions back.
+            InteractionData::ApplicationCommand(ac)
+                if interaction.kind == InteractionType::ApplicationCommandAutocomplete =>
+            {
+                crate::toy::autocomplete(*ac, state).await?
+            }
             InteractionData::ApplicationCommand(ac) => {
-                process_app_cmd(*ac, interaction.token, guild_id, invoker, state).await?
+                let context = InvokeContext {
+                    guild_id,
+                    invoker,
+                    roles,
+                    channel_id,
+                };
+                process_app_cmd(*ac, interaction.token, context, state).await?
             }
-            // this only applies to the leaderboard. It's the forward and back buttons.
             InteractionData::MessageComponent(mc) => {
-                crate::leaderboard::process_message_component(mc, guild_id, state).await?
+                if mc.custom_id.starts_with(crate::xp::RESET_ID_PREFIX) {
+                    crate::xp::process_reset_component(mc, guild_id, state).await?
+                } else if mc.custom_id.starts_with(crate::seasons::END_ID_PREFIX) {
+                    crate::seasons::process_end_component(mc, guild_id, state).await?
+                } else if mc.custom_id.starts_with(crate::card::CARD_ID_PREFIX) {
+                    crate::card::process_card_component(mc, invoker.id, state).await?
+                } else {
+                    // Everything else is the leaderboard. It's the forward and back buttons.
+                    crate::leaderboard::process_message_component(
+                        mc,
+                        guild_id,
+                        invoker.id,
+                        interaction.token,
+                        state,
+                    )
+                    .await?
+                }
             }
             InteractionData::ModalSubmit(ms) => {
-                crate::leaderboard::process_modal_submit(ms, guild_id, state).await?
+                crate::leaderboard::process_modal_submit(ms, guild_id, interaction.token, state)
+                    .await?
             }
             _ => PONG,
         };
@@ -47,17 +87,24 @@ pub async fn process_interaction(
     }
 }
 
+/// Who ran a command, and where.
+struct InvokeContext {
+    guild_id: Id<GuildMarker>,
+    invoker: User,
+    roles: Vec<Id<RoleMarker>>,
+    channel_id: Option<Id<ChannelMarker>>,
+}
+
 async fn process_app_cmd(
     data: CommandData,
     token: String,
-    guild_id: Id<GuildMarker>,
-    invoker: User,
+    context: InvokeContext,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
     match data.kind {
-        CommandType::ChatInput => process_slash_cmd(data, token, guild_id, invoker, state).await,
-        CommandType::User => process_user_cmd(data, token, invoker, state).await,
-        CommandType::Message => process_msg_cmd(data, token, invoker, state).await,
+        CommandType::ChatInput => process_slash_cmd(data, token, context, state).await,
+        CommandType::User => process_user_cmd(data, token, context.invoker, state).await,
+        CommandType::Message => process_msg_cmd(data, token, context.invoker, state).await,
         _ => Err(Error::WrongInteractionData),
     }
 }
@@ -65,24 +112,87 @@ async fn process_app_cmd(
 async fn process_slash_cmd(
     data: CommandData,
     token: String,
-    guild_id: Id<GuildMarker>,
-    invoker: User,
+    context: InvokeContext,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
+    let InvokeContext {
+        guild_id,
+        invoker,
+        roles,
+        channel_id,
+    } = context;
     match data.name.as_str() {
         "rank" => {
-            let target = crate::cmd_defs::RankCommand::from_interaction(data.into())?
-                .user
-                .map_or_else(|| invoker.clone(), |v| v.resolved);
-            crate::levels::get_level(guild_id, target, invoker, token, state).await
+            let cmd = crate::cmd_defs::RankCommand::from_interaction(data.into())?;
+            let target = cmd.user.map_or_else(|| invoker.clone(), |v| v.resolved);
+            if let Some(range) = cmd.history {
+                return Ok(crate::history::history(
+                    guild_id, target, range, token, state,
+                ));
+            }
+            // boosts only make sense for whoever is running the command
+            let boosts = match channel_id {
+                Some(channel_id) if target.id == invoker.id => {
+                    let config = state.configs.get(guild_id, &state.db).await?;
+                    Some(
+                        crate::multipliers::find_boosts(&config, &roles, channel_id, &state)
+                            .await?,
+                    )
+                }
+                _ => None,
+            };
+            crate::levels::get_level(guild_id, target, invoker, boosts, token, state).await
         }
         "leaderboard" => {
             let prefs = crate::cmd_defs::LeaderboardCommand::from_interaction(data.into())?;
-            crate::leaderboard::leaderboard(guild_id, state, prefs).await
+            crate::leaderboard::leaderboard(guild_id, token, state, prefs).await
         }
         "toy" => {
-            let selected = crate::cmd_defs::ToyCommand::from_interaction(data.into())?.toy_image;
-            crate::toy::modify(selected, guild_id, invoker, state).await
+            let cmd = crate::cmd_defs::ToyCommand::from_interaction(data.into())?;
+            let scope = cmd.scope.unwrap_or_default();
+            crate::toy::modify(cmd.toy_image, scope, guild_id, invoker, roles, state).await
+        }
+        "card" => {
+            let cmd = crate::cmd_defs::CardCommand::from_interaction(data.into())?;
+            crate::card::process_card(cmd, guild_id, invoker, token, state).await
+        }
+        "theme" => {
+            let cmd = crate::cmd_defs::ThemeCommand::from_interaction(data.into())?;
+            crate::card::process_theme(cmd, guild_id, state).await
+        }
+        "rewards" => {
+            let cmd = crate::cmd_defs::RewardsCommand::from_interaction(data.into())?;
+            crate::rewards::process_rewards(cmd, guild_id, state).await
+        }
+        "xp" => {
+            let cmd = crate::cmd_defs::XpCommand::from_interaction(data.into())?;
+            crate::xp::process_xp(cmd, guild_id, state).await
+        }
+        "config" => {
+            let cmd = crate::cmd_defs::ConfigCommand::from_interaction(data.into())?;
+            crate::config::process_config(cmd, guild_id, state).await
+        }
+        "exclusions" => {
+            let cmd = crate::cmd_defs::ExclusionsCommand::from_interaction(data.into())?;
+            crate::exclusions::process_exclusions(cmd, guild_id, state).await
+        }
+        "multipliers" => {
+            let cmd = crate::cmd_defs::MultipliersCommand::from_interaction(data.into())?;
+            crate::multipliers::process_multipliers(cmd, guild_id, state).await
+        }
+        "export" => {
+            let format = crate::cmd_defs::ExportCommand::from_interaction(data.into())?.format;
+            Ok(crate::export::process_export(
+                format, guild_id, token, state,
+            ))
+        }
+        "import" => {
+            let cmd = crate::cmd_defs::ImportCommand::from_interaction(data.into())?;
+            Ok(crate::import::process_import(cmd, guild_id, token, state))
+        }
+        "season" => {
+            let cmd = crate::cmd_defs::SeasonCommand::from_interaction(data.into())?;
+            crate::seasons::process_seasons(cmd, guild_id, state).await
         }
         _ => Err(Error::UnrecognizedCommand),
     }
@@ -106,6 +216,7 @@ async fn process_user_cmd(
         data.guild_id.ok_or(Error::NoGuildId)?,
         user.clone(),
         invoker,
+        None,
         token,
         state,
     )
@@ -131,6 +242,7 @@ async fn process_msg_cmd(
         data.guild_id.ok_or(Error::NoGuildId)?,
         user.clone(),
         invoker,
+        None,
         token,
         state,
     )
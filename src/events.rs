@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::Error;
+
+/// How long individual events are kept before they get rolled up into daily summaries,
+/// unless `XP_EVENT_RETENTION_DAYS` says otherwise.
+pub const DEFAULT_RETENTION_DAYS: i32 = 90;
+
+/// Why someone's XP changed. This is stored as text in `xp_events.source`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XpSource {
+    Message,
+    Admin,
+    Import,
+    Season,
+}
+
+impl XpSource {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Admin => "admin",
+            Self::Import => "import",
+            Self::Season => "season",
+        }
+    }
+}
+
+/// Writes one event to the log. Call this in the same transaction as the change to `levels`,
+/// so the two can never disagree.
+pub async fn record(
+    executor: impl sqlx::PgExecutor<'_>,
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    delta: i64,
+    source: XpSource,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO xp_events (guild, id, delta, source) VALUES ($1, $2, $3, $4)",
+        guild_id.get() as i64,
+        user_id.get() as i64,
+        delta,
+        source.as_str()
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Moves events older than `retention_days` into `xp_event_summaries`, one row per user,
+/// day and source. Returns how many summary rows were written.
+pub async fn roll_up(db: &sqlx::PgPool, retention_days: i32) -> Result<u64, Error> {
+    // The delete and insert are one statement, so events can't be lost or counted twice.
+    let rolled_up = query!(
+        "WITH moved AS (
+            DELETE FROM xp_events WHERE created_at < now() - make_interval(days => $1)
+            RETURNING guild, id, delta, source, created_at
+         )
+         INSERT INTO xp_event_summaries (guild, id, day, source, delta, events)
+         SELECT guild, id, created_at::DATE, source, SUM(delta), COUNT(*) FROM moved
+         GROUP BY guild, id, created_at::DATE, source
+         ON CONFLICT (guild, id, day, source) DO UPDATE SET
+         delta = xp_event_summaries.delta + excluded.delta,
+         events = xp_event_summaries.events + excluded.events",
+        retention_days
+    )
+    .execute(db)
+    .await?
+    .rows_affected();
+    Ok(rolled_up)
+}
+
+/// Runs [`roll_up`] once an hour, forever.
+pub async fn retention_task(db: sqlx::PgPool, retention_days: i32) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+        interval.tick().await;
+        match roll_up(&db, retention_days).await {
+            Ok(0) => {}
+            Ok(count) => info!("Rolled up old XP events into {count} summaries"),
+            Err(e) => warn!("Failed to roll up old XP events: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // These need a real database to run against, which CI doesn't have.
+    // Point TEST_DATABASE_URL at a scratch database to run them.
+    async fn test_db() -> Option<sqlx::PgPool> {
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            eprintln!("TEST_DATABASE_URL is not set, skipping");
+            return None;
+        };
+        let db = sqlx::PgPool::connect(&url)
+            .await
+            .expect("Failed to connect to TEST_DATABASE_URL");
+        sqlx::migrate!()
+            .run(&db)
+            .await
+            .expect("Failed to run migrations");
+        Some(db)
+    }
+
+    // Every test gets its own guild, so they can share a database.
+    fn test_guild() -> i64 {
+        i64::from(rand::random::<u32>()) + 1
+    }
+
+    async fn add_event(db: &sqlx::PgPool, guild: i64, id: i64, delta: i64, source: &str, at: &str) {
+        sqlx::query(
+            "INSERT INTO xp_events (guild, id, delta, source, created_at)
+             VALUES ($1, $2, $3, $4, $5::TIMESTAMPTZ)",
+        )
+        .bind(guild)
+        .bind(id)
+        .bind(delta)
+        .bind(source)
+        .bind(at)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn summaries(db: &sqlx::PgPool, guild: i64) -> Vec<(i64, String, String, i64, i64)> {
+        sqlx::query_as(
+            "SELECT id, day::TEXT, source, delta, events FROM xp_event_summaries
+             WHERE guild = $1 ORDER BY id, day, source",
+        )
+        .bind(guild)
+        .fetch_all(db)
+        .await
+        .unwrap()
+    }
+
+    async fn clean_up(db: &sqlx::PgPool, guild: i64) {
+        for table in ["xp_events", "xp_event_summaries"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE guild = $1"))
+                .bind(guild)
+                .execute(db)
+                .await
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn record_writes_one_event() {
+        let Some(db) = test_db().await else { return };
+        let guild = test_guild();
+        #[allow(clippy::cast_sign_loss)]
+        record(&db, Id::new(guild as u64), Id::new(7), -40, XpSource::Admin)
+            .await
+            .unwrap();
+        let events: Vec<(i64, i64, String, bool)> = sqlx::query_as(
+            "SELECT id, delta, source, created_at > now() - interval '1 minute'
+             FROM xp_events WHERE guild = $1",
+        )
+        .bind(guild)
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(events, vec![(7, -40, "admin".to_string(), true)]);
+        clean_up(&db, guild).await;
+    }
+
+    #[tokio::test]
+    async fn roll_up_sums_by_user_day_and_source() {
+        let Some(db) = test_db().await else { return };
+        let guild = test_guild();
+        add_event(&db, guild, 1, 20, "message", "2020-01-01 09:00:00+00").await;
+        add_event(&db, guild, 1, 25, "message", "2020-01-01 15:00:00+00").await;
+        add_event(&db, guild, 1, -10, "admin", "2020-01-01 16:00:00+00").await;
+        add_event(&db, guild, 1, 15, "message", "2020-01-02 12:00:00+00").await;
+        add_event(&db, guild, 2, 30, "message", "2020-01-01 12:00:00+00").await;
+        // too new to be rolled up
+        sqlx::query(
+            "INSERT INTO xp_events (guild, id, delta, source) VALUES ($1, 1, 5, 'message')",
+        )
+        .bind(guild)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        roll_up(&db, DEFAULT_RETENTION_DAYS).await.unwrap();
+
+        assert_eq!(
+            summaries(&db, guild).await,
+            vec![
+                (1, "2020-01-01".to_string(), "admin".to_string(), -10, 1),
+                (1, "2020-01-01".to_string(), "message".to_string(), 45, 2),
+                (1, "2020-01-02".to_string(), "message".to_string(), 15, 1),
+                (2, "2020-01-01".to_string(), "message".to_string(), 30, 1),
+            ]
+        );
+        let left: Vec<(i64,)> = sqlx::query_as("SELECT delta FROM xp_events WHERE guild = $1")
+            .bind(guild)
+            .fetch_all(&db)
+            .await
+            .unwrap();
+        assert_eq!(left, vec![(5,)]);
+        clean_up(&db, guild).await;
+    }
+
+    #[tokio::test]
+    async fn roll_up_adds_to_existing_summaries() {
+        let Some(db) = test_db().await else { return };
+        let guild = test_guild();
+        add_event(&db, guild, 1, 20, "message", "2020-01-01 12:00:00+00").await;
+        roll_up(&db, DEFAULT_RETENTION_DAYS).await.unwrap();
+        // an event that was late to the party, for a day that's already been summarized
+        add_event(&db, guild, 1, 25, "message", "2020-01-01 13:00:00+00").await;
+        roll_up(&db, DEFAULT_RETENTION_DAYS).await.unwrap();
+
+        assert_eq!(
+            summaries(&db, guild).await,
+            vec![(1, "2020-01-01".to_string(), "message".to_string(), 45, 2)]
+        );
+        clean_up(&db, guild).await;
+    }
+}
@@ -0,0 +1,241 @@
+use std::{fmt::Write, sync::Arc};
+
+use ahash::AHashMap;
+use parking_lot::RwLock;
+use twilight_model::{
+    gateway::payload::incoming::MessageCreate,
+    http::interaction::InteractionResponse,
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+
+use crate::{
+    cmd_defs::ExclusionsCommand, config::GuildConfig, ephemeral_embed_response, AppState, Error,
+};
+
+// A thread, its channel, and that channel's category
+const MAX_DEPTH: usize = 3;
+
+type ParentMap = AHashMap<Id<ChannelMarker>, Option<Id<ChannelMarker>>>;
+
+/// Remembers which channel each channel lives in, so we can check a thread's parent
+/// and a channel's category without asking Discord on every message.
+#[derive(Debug, Clone, Default)]
+pub struct ChannelParents {
+    parents: Arc<RwLock<ParentMap>>,
+}
+
+impl ChannelParents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    async fn get(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        client: &twilight_http::Client,
+    ) -> Result<Option<Id<ChannelMarker>>, Error> {
+        let cached = self.parents.read().get(&channel_id).copied();
+        if let Some(parent) = cached {
+            return Ok(parent);
+        }
+        let parent = client.channel(channel_id).await?.model().await?.parent_id;
+        self.parents.write().insert(channel_id, parent);
+        Ok(parent)
+    }
+    /// Walks up from a channel to its parent and then its category, returning the first
+    /// thing `check` finds. A thread's parent is a channel, and a channel's parent is a category,
+    /// so nothing is ever more than two levels deep.
+    pub async fn closest<T>(
+        &self,
+        channel_id: Id<ChannelMarker>,
+        client: &twilight_http::Client,
+        check: impl Fn(Id<ChannelMarker>) -> Option<T>,
+    ) -> Result<Option<T>, Error> {
+        let mut current = channel_id;
+        for depth in 1..=MAX_DEPTH {
+            if let Some(found) = check(current) {
+                return Ok(Some(found));
+            }
+            // There's no point fetching the parent of the last thing we're going to check.
+            if depth == MAX_DEPTH {
+                break;
+            }
+            let Some(parent) = self.get(current, client).await? else {
+                break;
+            };
+            current = parent;
+        }
+        Ok(None)
+    }
+    /// Call this when a channel changes, so the next lookup fetches it again.
+    pub fn forget(&self, channel_id: Id<ChannelMarker>) {
+        self.parents.write().remove(&channel_id);
+    }
+}
+
+pub async fn is_excluded(
+    msg: &MessageCreate,
+    config: &GuildConfig,
+    state: &AppState,
+) -> Result<bool, Error> {
+    if let Some(member) = &msg.member {
+        if member
+            .roles
+            .iter()
+            .any(|role| config.excluded_roles.contains(role))
+        {
+            return Ok(true);
+        }
+    }
+    // Most guilds don't exclude any channels, and then we don't need to look up parents at all.
+    if config.excluded_channels.is_empty() {
+        return Ok(false);
+    }
+    Ok(state
+        .channel_parents
+        .closest(msg.channel_id, &state.client, |channel| {
+            config.excluded_channels.contains(&channel).then_some(())
+        })
+        .await?
+        .is_some())
+}
+
+pub async fn process_exclusions(
+    cmd: ExclusionsCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let description = match cmd {
+        ExclusionsCommand::Add(add) => match (add.channel, add.role) {
+            (Some(channel), None) => {
+                add_channel(channel, guild_id, &state).await?;
+                format!("Messages in <#{channel}> will no longer earn XP.")
+            }
+            (None, Some(role)) => {
+                add_role(role, guild_id, &state).await?;
+                format!("Members with <@&{role}> will no longer earn XP.")
+            }
+            _ => return Err(Error::NoChannelOrRole),
+        },
+        ExclusionsCommand::Remove(remove) => match (remove.channel, remove.role) {
+            (Some(channel), None) => {
+                remove_channel(channel, guild_id, &state).await?;
+                format!("Messages in <#{channel}> can earn XP again.")
+            }
+            (None, Some(role)) => {
+                remove_role(role, guild_id, &state).await?;
+                format!("Members with <@&{role}> can earn XP again.")
+            }
+            _ => return Err(Error::NoChannelOrRole),
+        },
+        ExclusionsCommand::List(_) => {
+            let config = state.configs.get(guild_id, &state.db).await?;
+            list_exclusions(&config)
+        }
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+async fn add_channel(
+    channel: Id<ChannelMarker>,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO excluded_channels (id, guild) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        channel.get() as i64,
+        guild_id.get() as i64
+    )
+    .execute(&state.db)
+    .await?;
+    let mut config = (*state.configs.get(guild_id, &state.db).await?).clone();
+    config.excluded_channels.insert(channel);
+    state.configs.set(guild_id, config);
+    Ok(())
+}
+
+async fn remove_channel(
+    channel: Id<ChannelMarker>,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "DELETE FROM excluded_channels WHERE id = $1 AND guild = $2",
+        channel.get() as i64,
+        guild_id.get() as i64
+    )
+    .execute(&state.db)
+    .await?;
+    let mut config = (*state.configs.get(guild_id, &state.db).await?).clone();
+    config.excluded_channels.remove(&channel);
+    state.configs.set(guild_id, config);
+    Ok(())
+}
+
+async fn add_role(
+    role: Id<RoleMarker>,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO excluded_roles (id, guild) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        role.get() as i64,
+        guild_id.get() as i64
+    )
+    .execute(&state.db)
+    .await?;
+    let mut config = (*state.configs.get(guild_id, &state.db).await?).clone();
+    config.excluded_roles.insert(role);
+    state.configs.set(guild_id, config);
+    Ok(())
+}
+
+async fn remove_role(
+    role: Id<RoleMarker>,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "DELETE FROM excluded_roles WHERE id = $1 AND guild = $2",
+        role.get() as i64,
+        guild_id.get() as i64
+    )
+    .execute(&state.db)
+    .await?;
+    let mut config = (*state.configs.get(guild_id, &state.db).await?).clone();
+    config.excluded_roles.remove(&role);
+    state.configs.set(guild_id, config);
+    Ok(())
+}
+
+fn list_exclusions(config: &GuildConfig) -> String {
+    if config.excluded_channels.is_empty() && config.excluded_roles.is_empty() {
+        return "Every channel and role can earn XP in this server.".to_string();
+    }
+    let mut description =
+        String::with_capacity((config.excluded_channels.len() + config.excluded_roles.len()) * 32);
+    if !config.excluded_channels.is_empty() {
+        description += "**Channels:**\n";
+        for channel in &config.excluded_channels {
+            writeln!(description, "<#{channel}>").ok();
+        }
+    }
+    if !config.excluded_roles.is_empty() {
+        description += "**Roles:**\n";
+        for role in &config.excluded_roles {
+            writeln!(description, "<@&{role}>").ok();
+        }
+    }
+    description
+}
@@ -0,0 +1,124 @@
+use std::fmt::Write;
+
+use serde::Serialize;
+use twilight_interactions::command::{CommandOption, CreateOption};
+use twilight_model::{
+    channel::message::{Embed, MessageFlags},
+    http::{
+        attachment::Attachment,
+        interaction::{InteractionResponse, InteractionResponseType},
+    },
+    id::{marker::GuildMarker, Id},
+};
+use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
+
+use crate::{AppState, Error};
+
+#[derive(Clone, Copy, Debug, CreateOption, CommandOption)]
+pub enum ExportFormat {
+    #[option(name = "CSV", value = "csv")]
+    Csv,
+    #[option(name = "JSON", value = "json")]
+    Json,
+}
+
+#[derive(Serialize)]
+struct ExportRow {
+    // snowflakes are too big for javascript numbers, so they go out as strings
+    id: String,
+    xp: i64,
+    level: u64,
+    rank: i64,
+}
+
+pub fn process_export(
+    format: ExportFormat,
+    guild_id: Id<GuildMarker>,
+    token: String,
+    state: AppState,
+) -> InteractionResponse {
+    // Big servers have a lot of levels, so we answer later instead of making discord wait.
+    tokio::spawn(async move {
+        let (embed, attachments) = match export(format, guild_id, &state).await {
+            Ok((embed, attachment)) => (embed, vec![attachment]),
+            Err(e) => {
+                let embed = EmbedBuilder::new()
+                    .description(format!("❌ {e}"))
+                    .color(crate::THEME_COLOR)
+                    .build();
+                (embed, Vec::new())
+            }
+        };
+        let embeds = [embed];
+        match state
+            .client
+            .interaction(state.my_id)
+            .create_followup(&token)
+            .embeds(&embeds)
+            .and_then(|followup| followup.attachments(&attachments))
+        {
+            Ok(followup) => {
+                if let Err(e) = followup.await {
+                    warn!("{e:#?}");
+                }
+            }
+            Err(e) => warn!("{e:#?}"),
+        }
+    });
+    InteractionResponse {
+        kind: InteractionResponseType::DeferredChannelMessageWithSource,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+        ),
+    }
+}
+
+async fn export(
+    format: ExportFormat,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<(Embed, Attachment), Error> {
+    // RANK() gives tied users the same rank, just like /rank does
+    #[allow(clippy::cast_possible_wrap)]
+    let rows: Vec<ExportRow> = query!(
+        "SELECT id, xp, RANK() OVER (ORDER BY xp DESC) AS rank FROM levels WHERE guild = $1 ORDER BY xp DESC",
+        guild_id.get() as i64
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|row| {
+        #[allow(clippy::cast_sign_loss)]
+        ExportRow {
+            id: (row.id as u64).to_string(),
+            xp: row.xp,
+            level: mee6::LevelInfo::new(row.xp as u64).level(),
+            rank: row.rank.unwrap_or(0),
+        }
+    })
+    .collect();
+    let (file, filename) = match format {
+        ExportFormat::Csv => {
+            let mut csv = String::with_capacity(rows.len() * 48 + 20);
+            csv += "id,xp,level,rank\n";
+            for row in &rows {
+                writeln!(csv, "{},{},{},{}", row.id, row.xp, row.level, row.rank).ok();
+            }
+            (csv.into_bytes(), "levels.csv")
+        }
+        ExportFormat::Json => (serde_json::to_vec(&rows)?, "levels.json"),
+    };
+    let attachment = Attachment {
+        description: Some(format!("Levels for {} users", rows.len())),
+        file,
+        filename: filename.to_string(),
+        id: 0,
+    };
+    let embed = EmbedBuilder::new()
+        .description(format!("Exported levels for {} users.", rows.len()))
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok((embed, attachment))
+}
@@ -10,7 +10,24 @@ use crate::{AppState, Error};
 pub async fn handle(interaction: Interaction, state: AppState) -> Result<(), Error> {
     let interaction_token = interaction.token.clone();
     let interaction_id = interaction.id;
-    let response = match crate::dispatch::process_interaction(interaction, state.clone()).await {
+    let response = respond(interaction, state.clone()).await;
+    state
+        .client
+        .interaction(state.my_id)
+        .create_response(interaction_id, &interaction_token, &response)
+        .await?;
+    Ok(())
+}
+
+/// Works out what to answer an interaction with, turning errors into a message for the user.
+/// Interactions from the gateway get this sent back over HTTP, and ones from the
+/// interactions endpoint get it as the reply.
+pub async fn respond(interaction: Interaction, state: AppState) -> InteractionResponse {
+    let command = crate::metrics::command_name(&interaction);
+    let metrics = state.metrics.clone();
+    let result = Box::pin(crate::dispatch::process_interaction(interaction, state)).await;
+    metrics.interaction(&command, result.as_ref().err());
+    match result {
         Ok(val) => val,
         Err(e) => {
             // this often produces errors that are not bugs. Thus, warn rather then error.
@@ -27,11 +44,5 @@ pub async fn handle(interaction: Interaction, state: AppState) -> Result<(), Err
                 ),
             }
         }
-    };
-    state
-        .client
-        .interaction(state.my_id)
-        .create_response(interaction_id, &interaction_token, &response)
-        .await?;
-    Ok(())
+    }
 }
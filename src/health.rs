@@ -0,0 +1,105 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use twilight_gateway::Shard;
+
+// A database that takes longer than this to answer might as well be down
+const DB_TIMEOUT: Duration = Duration::from_secs(2);
+// What discord usually asks for, until a shard's Hello tells us otherwise
+const DEFAULT_HEARTBEAT_MS: u64 = 41_250;
+// Missing this many heartbeat acks in a row means the shard is stuck, whatever its status says
+const MISSED_HEARTBEATS: u64 = 2;
+
+#[derive(Default)]
+struct ShardState {
+    connected: AtomicBool,
+    // Milliseconds after `ShardHealth::started` that the shard last gave us anything
+    last_seen: AtomicU64,
+    heartbeat_ms: AtomicU64,
+}
+
+/// Whether each shard is connected and still talking to us, indexed by shard number.
+#[derive(Clone)]
+pub struct ShardHealth {
+    started: Instant,
+    shards: Arc<[ShardState]>,
+}
+
+impl ShardHealth {
+    pub fn new(shard_count: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            shards: (0..shard_count)
+                .map(|_| ShardState {
+                    heartbeat_ms: AtomicU64::new(DEFAULT_HEARTBEAT_MS),
+                    ..ShardState::default()
+                })
+                .collect(),
+        }
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    fn get(&self, shard: &Shard) -> Option<&ShardState> {
+        self.shards.get(shard.id().number() as usize)
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    fn now_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+    /// Records where a shard is at. This is called every time the shard gives us anything,
+    /// including heartbeat acks, so a shard that goes quiet goes stale.
+    pub fn update(&self, shard: &Shard) {
+        if let Some(state) = self.get(shard) {
+            // Connected means identified too, resuming doesn't count until it's done.
+            state
+                .connected
+                .store(shard.status().is_connected(), Ordering::Relaxed);
+            state.last_seen.store(self.now_ms(), Ordering::Relaxed);
+        }
+    }
+    /// Records how often discord asked the shard to heartbeat, from its Hello.
+    pub fn heartbeat_interval(&self, shard: &Shard, interval_ms: u64) {
+        if let Some(state) = self.get(shard) {
+            state.heartbeat_ms.store(interval_ms, Ordering::Relaxed);
+        }
+    }
+    fn all_connected(&self) -> bool {
+        let now = self.now_ms();
+        self.shards.iter().all(|state| {
+            let quiet_for = now.saturating_sub(state.last_seen.load(Ordering::Relaxed));
+            state.connected.load(Ordering::Relaxed)
+                && quiet_for <= state.heartbeat_ms.load(Ordering::Relaxed) * MISSED_HEARTBEATS
+        })
+    }
+}
+
+#[derive(Clone)]
+struct HealthState {
+    db: sqlx::PgPool,
+    shards: ShardHealth,
+}
+
+/// `/healthz` answers as long as the process does. `/readyz` only says yes while the
+/// database answers and every shard is connected and has heard from discord recently.
+pub fn router(db: sqlx::PgPool, shards: ShardHealth) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(ready))
+        .with_state(HealthState { db, shards })
+}
+
+async fn ready(State(state): State<HealthState>) -> (StatusCode, &'static str) {
+    let probe = tokio::time::timeout(DB_TIMEOUT, query!("SELECT 1 AS one").fetch_one(&state.db));
+    if !matches!(probe.await, Ok(Ok(_))) {
+        return (StatusCode::SERVICE_UNAVAILABLE, "database unreachable");
+    }
+    if !state.shards.all_connected() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "shards not connected");
+    }
+    (StatusCode::OK, "ready")
+}
@@ -0,0 +1,295 @@
+use std::{fmt::Write, sync::Arc};
+
+use resvg::usvg::{TreeParsing, TreeTextToPath};
+use twilight_interactions::command::{CommandOption, CreateOption};
+use twilight_model::{
+    http::{
+        attachment::Attachment,
+        interaction::{InteractionResponse, InteractionResponseType},
+    },
+    id::{marker::GuildMarker, Id},
+    user::User,
+};
+use twilight_util::builder::embed::EmbedBuilder;
+
+use crate::{AppState, Error};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 400.0;
+// where the lines go, leaving room for the title and axis labels
+const PLOT_LEFT: f64 = 80.0;
+const PLOT_RIGHT: f64 = 720.0;
+const PLOT_TOP: f64 = 70.0;
+const PLOT_BOTTOM: f64 = 340.0;
+const XP_COLOR: &str = "#7289da";
+const LEVEL_COLOR: &str = "#f0b232";
+
+#[derive(Clone, Copy, Debug, CreateOption, CommandOption)]
+pub enum HistoryRange {
+    #[option(name = "Last 30 days", value = "30")]
+    Month,
+    #[option(name = "Last 90 days", value = "90")]
+    Quarter,
+    #[option(name = "Last 365 days", value = "365")]
+    Year,
+}
+
+impl HistoryRange {
+    const fn days(self) -> i32 {
+        match self {
+            Self::Month => 30,
+            Self::Quarter => 90,
+            Self::Year => 365,
+        }
+    }
+}
+
+/// Renders SVG charts to PNGs. This is the same resvg setup the rank cards use, but with
+/// our own SVG instead of the card template.
+#[derive(Clone)]
+pub struct ChartRenderer {
+    fonts: Arc<resvg::usvg::fontdb::Database>,
+}
+
+impl ChartRenderer {
+    pub fn new() -> Self {
+        let mut fonts = resvg::usvg::fontdb::Database::new();
+        fonts.load_font_data(xpd_rank_card::Font::Roboto.ttf().to_vec());
+        Self {
+            fonts: Arc::new(fonts),
+        }
+    }
+    /// Rendering takes long enough that it shouldn't happen on the async threads.
+    // The closure's error goes straight back out through the `?`, so its size doesn't matter.
+    #[allow(clippy::result_large_err)]
+    pub async fn render(&self, svg: String) -> Result<Vec<u8>, Error> {
+        let fonts = self.fonts.clone();
+        tokio::task::spawn_blocking(move || {
+            let opt = resvg::usvg::Options {
+                font_family: "Roboto".to_string(),
+                ..Default::default()
+            };
+            let mut tree = resvg::usvg::Tree::from_str(&svg, &opt)?;
+            tree.convert_text(&fonts);
+            let size = tree.size.to_int_size();
+            let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+                .ok_or(Error::ChartPixmap)?;
+            resvg::Tree::from_usvg(&tree)
+                .render(resvg::tiny_skia::Transform::default(), &mut pixmap.as_mut());
+            Ok(pixmap.encode_png()?)
+        })
+        .await?
+    }
+}
+
+pub fn history(
+    guild_id: Id<GuildMarker>,
+    user: User,
+    range: HistoryRange,
+    token: String,
+    state: AppState,
+) -> InteractionResponse {
+    // Charts take a moment to draw, so we answer later instead of making discord wait.
+    tokio::spawn(async move {
+        let Err(err) = send_chart(guild_id, &user, range, &token, &state).await else {
+            return;
+        };
+        let embed = EmbedBuilder::new().description(err.to_string()).build();
+        let embeds = [embed];
+        match state
+            .client
+            .interaction(state.my_id)
+            .create_followup(&token)
+            .embeds(&embeds)
+        {
+            Ok(awaitable) => {
+                if let Err(e) = awaitable.await {
+                    warn!("{e:#?}");
+                }
+            }
+            Err(e) => warn!("{e:#?}"),
+        }
+    });
+    InteractionResponse {
+        kind: InteractionResponseType::DeferredChannelMessageWithSource,
+        data: None,
+    }
+}
+
+async fn send_chart(
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    range: HistoryRange,
+    token: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    if user.bot {
+        return Err(Error::BotsNotRanked);
+    }
+    let totals = daily_totals(guild_id, user, range.days(), &state.db).await?;
+    let png = state
+        .charts
+        .render(chart_svg(&user.name, range.days(), &totals))
+        .await?;
+    let first = totals.first().copied().unwrap_or(0);
+    let last = totals.last().copied().unwrap_or(0);
+    let chart = Attachment {
+        description: Some(format!(
+            "{} went from {first} XP to {last} XP over the last {} days.",
+            user.name,
+            range.days()
+        )),
+        file: png,
+        filename: "history.png".to_string(),
+        id: 0,
+    };
+    let attachments = [chart];
+    state
+        .client
+        .interaction(state.my_id)
+        .create_followup(token)
+        .attachments(&attachments)?
+        .await?;
+    Ok(())
+}
+
+/// Returns how much XP the user had at the end of each of the last `days` days, plus today,
+/// oldest first. We only know the changes, so this works backwards from their XP right now.
+async fn daily_totals(
+    guild_id: Id<GuildMarker>,
+    user: &User,
+    days: i32,
+    db: &sqlx::PgPool,
+) -> Result<Vec<i64>, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let (guild, id) = (guild_id.get() as i64, user.id.get() as i64);
+    let current = query!(
+        "SELECT xp FROM levels WHERE id = $1 AND guild = $2",
+        id,
+        guild
+    )
+    .fetch_optional(db)
+    .await?
+    .map_or(0, |v| v.xp);
+    // Old events have been rolled up into days already, so the two tables fit together.
+    // Unlike the period leaderboards, every source counts here, since we're working back
+    // from a total that includes all of them.
+    let changes = query!(
+        r#"SELECT (current_date - day)::INT AS "days_ago!", SUM(delta)::BIGINT AS "delta!" FROM (
+            SELECT created_at::DATE AS day, delta FROM xp_events
+            WHERE guild = $1 AND id = $2 AND created_at >= current_date - $3::INT
+            UNION ALL
+            SELECT day, delta FROM xp_event_summaries
+            WHERE guild = $1 AND id = $2 AND day >= current_date - $3::INT
+         ) AS changes GROUP BY day"#,
+        guild,
+        id,
+        days
+    )
+    .fetch_all(db)
+    .await?;
+    #[allow(clippy::cast_sign_loss)]
+    let mut deltas = vec![0; days as usize + 1];
+    for change in changes {
+        #[allow(clippy::cast_sign_loss)]
+        if let Some(delta) = deltas.get_mut(change.days_ago as usize) {
+            *delta += change.delta;
+        }
+    }
+    // deltas[0] is today. The total at the end of a day is the current total, minus
+    // everything that was earned after it.
+    let mut totals = Vec::with_capacity(deltas.len());
+    let mut total = current;
+    for delta in deltas {
+        totals.push(total.max(0));
+        total -= delta;
+    }
+    totals.reverse();
+    Ok(totals)
+}
+
+#[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+fn chart_svg(name: &str, days: i32, totals: &[i64]) -> String {
+    let levels: Vec<u64> = totals
+        .iter()
+        .map(|xp| mee6::LevelInfo::new(*xp as u64).level())
+        .collect();
+    let min_xp = totals.iter().copied().min().unwrap_or(0);
+    let max_xp = totals.iter().copied().max().unwrap_or(0).max(min_xp + 1);
+    let min_level = levels.iter().copied().min().unwrap_or(0);
+    let max_level = levels.iter().copied().max().unwrap_or(0).max(min_level + 1);
+    let step = (PLOT_RIGHT - PLOT_LEFT) / (totals.len().max(2) - 1) as f64;
+    let x = |i: usize| (i as f64).mul_add(step, PLOT_LEFT);
+    let y = |value: f64, min: f64, max: f64| {
+        ((value - min) / (max - min)).mul_add(PLOT_TOP - PLOT_BOTTOM, PLOT_BOTTOM)
+    };
+    let xp_y = |xp: i64| y(xp as f64, min_xp as f64, max_xp as f64);
+    let level_y = |level: u64| y(level as f64, min_level as f64, max_level as f64);
+
+    let mut svg = String::with_capacity(totals.len() * 48 + 2048);
+    write!(
+        svg,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}">
+<rect width="100%" height="100%" rx="16" fill="#1e1f22"/>
+<text x="{PLOT_LEFT}" y="42" font-size="24" fill="#ffffff">{}'s XP over the last {days} days</text>
+"##,
+        escape(name)
+    )
+    .ok();
+    // gridlines, with XP on the left axis
+    for i in 0..=4 {
+        let value = min_xp + (max_xp - min_xp) * i / 4;
+        let line_y = xp_y(value);
+        write!(
+            svg,
+            r##"<line x1="{PLOT_LEFT}" y1="{line_y}" x2="{PLOT_RIGHT}" y2="{line_y}" stroke="#3f4147"/>
+<text x="{}" y="{}" font-size="14" fill="{XP_COLOR}" text-anchor="end">{value}</text>
+"##,
+            PLOT_LEFT - 8.0,
+            line_y + 5.0
+        )
+        .ok();
+    }
+    write!(
+        svg,
+        r##"<text x="{}" y="{}" font-size="14" fill="{LEVEL_COLOR}">Level {max_level}</text>
+<text x="{}" y="{}" font-size="14" fill="{LEVEL_COLOR}">Level {min_level}</text>
+<text x="{PLOT_LEFT}" y="{}" font-size="14" fill="#b5bac1">{days} days ago</text>
+<text x="{PLOT_RIGHT}" y="{}" font-size="14" fill="#b5bac1" text-anchor="end">Today</text>
+"##,
+        PLOT_RIGHT + 8.0,
+        level_y(max_level) + 5.0,
+        PLOT_RIGHT + 8.0,
+        level_y(min_level) + 5.0,
+        PLOT_BOTTOM + 28.0,
+        PLOT_BOTTOM + 28.0
+    )
+    .ok();
+    // Levels only ever jump, so they're drawn as steps instead of slopes.
+    svg += r#"<path fill="none" stroke-width="2" stroke-dasharray="6 4" stroke=""#;
+    svg += LEVEL_COLOR;
+    svg += r#"" d=""#;
+    for (i, level) in levels.iter().enumerate() {
+        if i == 0 {
+            write!(svg, "M{} {}", x(i), level_y(*level)).ok();
+        } else {
+            write!(svg, " H{} V{}", x(i), level_y(*level)).ok();
+        }
+    }
+    svg += "\"/>\n<polyline fill=\"none\" stroke-width=\"3\" stroke-linejoin=\"round\" stroke=\"";
+    svg += XP_COLOR;
+    svg += "\" points=\"";
+    for (i, xp) in totals.iter().enumerate() {
+        write!(svg, "{},{} ", x(i), xp_y(*xp)).ok();
+    }
+    svg += "\"/>\n</svg>";
+    svg
+}
+
+// Usernames end up inside the SVG, so they can't be allowed to close tags.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
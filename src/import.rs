@@ -0,0 +1,425 @@
+use std::fmt::Write;
+
+use ahash::AHashSet;
+use serde::Deserialize;
+use twilight_interactions::command::{CommandOption, CreateOption};
+use twilight_model::{
+    channel::message::MessageFlags,
+    http::interaction::{InteractionResponse, InteractionResponseType},
+    id::{marker::GuildMarker, Id},
+};
+use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
+
+use crate::{
+    cmd_defs::{ImportCommand, ImportFile},
+    events::XpSource,
+    AppState, Error,
+};
+
+/// Where MEE6's public leaderboards live. Override it with `MEE6_API_URL` to test against something else.
+pub const MEE6_DEFAULT_URL: &str = "https://mee6.xyz/api/plugins/levels/leaderboard";
+// this is the most MEE6 will give us in one page
+const MEE6_PAGE_SIZE: usize = 1000;
+// files bigger than this are almost certainly not levels
+const MAX_IMPORT_FILE_SIZE: u64 = 8 * 1024 * 1024;
+// embeds can only be so long, so past this we just say how many more there were
+const MAX_REPORTED_REJECTIONS: usize = 20;
+
+#[derive(Clone, Copy, Debug, CreateOption, CommandOption)]
+pub enum ImportMode {
+    #[option(name = "Replace existing XP", value = "overwrite")]
+    Overwrite,
+    #[option(name = "Add to existing XP", value = "add")]
+    Add,
+}
+
+#[derive(Deserialize)]
+struct Mee6Page {
+    players: Vec<Mee6Player>,
+}
+
+#[derive(Deserialize)]
+struct Mee6Player {
+    id: String,
+    xp: i64,
+}
+
+pub fn process_import(
+    cmd: ImportCommand,
+    guild_id: Id<GuildMarker>,
+    token: String,
+    state: AppState,
+) -> InteractionResponse {
+    // Imports can take a while, so we answer later instead of making discord wait.
+    tokio::spawn(async move {
+        let result = match cmd {
+            ImportCommand::Mee6(_) => {
+                import_mee6(guild_id, &state.db, &state.http, &state.mee6_url)
+                    .await
+                    .map(|count| format!("Imported XP for {count} users from MEE6!"))
+            }
+            ImportCommand::File(file) => import_file(file, guild_id, &state).await,
+        };
+        let description = result.unwrap_or_else(|e| format!("❌ {e}"));
+        send_followup(&state, &token, description).await;
+    });
+    InteractionResponse {
+        kind: InteractionResponseType::DeferredChannelMessageWithSource,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+        ),
+    }
+}
+
+async fn send_followup(state: &AppState, token: &str, description: String) {
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    let embeds = [embed];
+    match state
+        .client
+        .interaction(state.my_id)
+        .create_followup(token)
+        .embeds(&embeds)
+    {
+        Ok(awaitable) => {
+            if let Err(e) = awaitable.await {
+                warn!("{e:#?}");
+            }
+        }
+        Err(e) => warn!("{e:#?}"),
+    }
+}
+
+/// Copies a guild's whole MEE6 leaderboard into `levels`, replacing the XP of anyone
+/// who is on both. Returns how many users were imported.
+pub async fn import_mee6(
+    guild_id: Id<GuildMarker>,
+    db: &sqlx::PgPool,
+    http: &reqwest::Client,
+    base_url: &str,
+) -> Result<usize, Error> {
+    let mut ids: Vec<i64> = Vec::new();
+    let mut xps: Vec<i64> = Vec::new();
+    // Download everything before touching the database, so a failure halfway leaves nothing behind.
+    for page in 0.. {
+        let response = http
+            .get(format!("{base_url}/{guild_id}"))
+            .query(&[("page", page), ("limit", MEE6_PAGE_SIZE)])
+            .send()
+            .await?;
+        let status = response.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::NOT_FOUND {
+            return Err(Error::Mee6LeaderboardUnavailable);
+        }
+        let players = response
+            .error_for_status()?
+            .json::<Mee6Page>()
+            .await?
+            .players;
+        let last_page = players.len() < MEE6_PAGE_SIZE;
+        for player in players {
+            let id: i64 = player
+                .id
+                .parse()
+                .map_err(|_| Error::InvalidMee6UserId(player.id))?;
+            ids.push(id);
+            xps.push(player.xp.max(0));
+        }
+        if last_page {
+            break;
+        }
+    }
+    let mut txn = db.begin().await?;
+    record_import_events(&mut txn, guild_id, &ids, &xps, ImportMode::Overwrite).await?;
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO levels (id, xp, guild) SELECT id, xp, $3 FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS t(id, xp)
+         ON CONFLICT (id, guild) DO UPDATE SET xp = excluded.xp",
+        &ids,
+        &xps,
+        guild_id.get() as i64
+    )
+    .execute(&mut txn)
+    .await?;
+    txn.commit().await?;
+    Ok(ids.len())
+}
+
+async fn import_file(
+    options: ImportFile,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<String, Error> {
+    if options.file.size > MAX_IMPORT_FILE_SIZE {
+        return Err(Error::ImportFileTooLarge);
+    }
+    let text = state
+        .http
+        .get(&options.file.url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let is_json = options
+        .file
+        .filename
+        .to_ascii_lowercase()
+        .ends_with(".json")
+        || text.trim_start().starts_with('[');
+    let rows = if is_json {
+        parse_json(serde_json::from_str(&text)?).ok_or(Error::ImportNotJsonArray)?
+    } else {
+        parse_csv(&text)
+    };
+    let mut txn = state.db.begin().await?;
+    record_import_events(&mut txn, guild_id, &rows.ids, &rows.xps, options.mode).await?;
+    #[allow(clippy::cast_possible_wrap)]
+    match options.mode {
+        ImportMode::Overwrite => {
+            query!(
+                "INSERT INTO levels (id, xp, guild) SELECT id, xp, $3 FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS t(id, xp)
+                 ON CONFLICT (id, guild) DO UPDATE SET xp = excluded.xp",
+                &rows.ids,
+                &rows.xps,
+                guild_id.get() as i64
+            )
+            .execute(&mut txn)
+            .await?;
+        }
+        ImportMode::Add => {
+            // Checking first gives a proper error, instead of postgres failing the whole insert.
+            let overflow = query!(
+                "SELECT levels.id FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS t(id, xp)
+                 JOIN levels ON levels.id = t.id AND levels.guild = $3
+                 WHERE levels.xp > 9223372036854775807 - t.xp LIMIT 1",
+                &rows.ids,
+                &rows.xps,
+                guild_id.get() as i64
+            )
+            .fetch_optional(&mut txn)
+            .await?;
+            if let Some(overflow) = overflow {
+                return Err(Error::ImportTooMuchXp(overflow.id));
+            }
+            query!(
+                "INSERT INTO levels (id, xp, guild) SELECT id, xp, $3 FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS t(id, xp)
+                 ON CONFLICT (id, guild) DO UPDATE SET xp = levels.xp + excluded.xp",
+                &rows.ids,
+                &rows.xps,
+                guild_id.get() as i64
+            )
+            .execute(&mut txn)
+            .await?;
+        }
+    }
+    txn.commit().await?;
+    let mut summary = format!("Imported XP for {} users!", rows.ids.len());
+    if !rows.rejected.is_empty() {
+        write!(summary, "\n\nSkipped {} entries:", rows.rejected.len()).ok();
+        for rejection in rows.rejected.iter().take(MAX_REPORTED_REJECTIONS) {
+            write!(summary, "\n{rejection}").ok();
+        }
+        if rows.rejected.len() > MAX_REPORTED_REJECTIONS {
+            write!(
+                summary,
+                "\n...and {} more",
+                rows.rejected.len() - MAX_REPORTED_REJECTIONS
+            )
+            .ok();
+        }
+    }
+    Ok(summary)
+}
+
+// This has to run before levels is changed, because overwrites log the difference from the old XP.
+async fn record_import_events(
+    txn: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    guild_id: Id<GuildMarker>,
+    ids: &[i64],
+    xps: &[i64],
+    mode: ImportMode,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO xp_events (guild, id, delta, source)
+         SELECT $3, t.id, t.xp - CASE WHEN $4 THEN COALESCE(levels.xp, 0) ELSE 0 END, $5
+         FROM UNNEST($1::BIGINT[], $2::BIGINT[]) AS t(id, xp)
+         LEFT JOIN levels ON levels.id = t.id AND levels.guild = $3",
+        ids,
+        xps,
+        guild_id.get() as i64,
+        matches!(mode, ImportMode::Overwrite),
+        XpSource::Import.as_str()
+    )
+    .execute(txn)
+    .await?;
+    Ok(())
+}
+
+/// Rows from an import file that passed validation, and why the others didn't.
+#[derive(Default)]
+struct ImportRows {
+    ids: Vec<i64>,
+    xps: Vec<i64>,
+    rejected: Vec<String>,
+    seen: AHashSet<i64>,
+}
+
+impl ImportRows {
+    fn push(&mut self, location: &str, id: &str, xp: &str) {
+        // snowflakes are always positive and fit in an i64, which is how we store them
+        let Some(id) = id.parse::<i64>().ok().filter(|id| *id > 0) else {
+            self.rejected
+                .push(format!("{location}: `{id}` is not a valid user ID"));
+            return;
+        };
+        let Some(xp) = xp.parse::<i64>().ok().filter(|xp| *xp >= 0) else {
+            self.rejected
+                .push(format!("{location}: `{xp}` is not a valid amount of XP"));
+            return;
+        };
+        // postgres won't update the same row twice in one statement
+        if !self.seen.insert(id) {
+            self.rejected.push(format!(
+                "{location}: user {id} is in the file more than once"
+            ));
+            return;
+        }
+        self.ids.push(id);
+        self.xps.push(xp);
+    }
+    fn reject(&mut self, location: &str) {
+        self.rejected
+            .push(format!("{location}: needs both an id and xp"));
+    }
+}
+
+// CSV files need an id and xp column, in that order. Anything after that, like the level
+// and rank columns from /export, is ignored. A header line is allowed.
+fn parse_csv(text: &str) -> ImportRows {
+    let mut rows = ImportRows::default();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let location = format!("Line {}", index + 1);
+        let mut fields = line.split(',').map(str::trim);
+        let (Some(id), Some(xp)) = (fields.next(), fields.next()) else {
+            rows.reject(&location);
+            continue;
+        };
+        if index == 0 && id.eq_ignore_ascii_case("id") {
+            continue;
+        }
+        rows.push(&location, id, xp);
+    }
+    rows
+}
+
+// JSON files are a list of objects with id and xp fields. IDs can be strings or numbers.
+fn parse_json(value: serde_json::Value) -> Option<ImportRows> {
+    let serde_json::Value::Array(entries) = value else {
+        return None;
+    };
+    let mut rows = ImportRows::default();
+    for (index, entry) in entries.iter().enumerate() {
+        let location = format!("Entry {}", index + 1);
+        let (Some(id), Some(xp)) = (
+            entry.get("id").and_then(json_field),
+            entry.get("xp").and_then(json_field),
+        ) else {
+            rows.reject(&location);
+            continue;
+        };
+        rows.push(&location, &id, &xp);
+    }
+    Some(rows)
+}
+
+fn json_field(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_with_header_and_extra_columns() {
+        let rows = parse_csv("id,xp,level,rank\n123,456,2,1\n\n 789 , 10 \n");
+        assert_eq!(rows.ids, [123, 789]);
+        assert_eq!(rows.xps, [456, 10]);
+        assert!(rows.rejected.is_empty());
+    }
+
+    #[test]
+    fn csv_rejections() {
+        let rows = parse_csv("123\nabc,5\n-4,5\n5,-1\n5,1\n5,2\n");
+        assert_eq!(rows.ids, [5]);
+        assert_eq!(rows.xps, [1]);
+        assert_eq!(
+            rows.rejected,
+            [
+                "Line 1: needs both an id and xp",
+                "Line 2: `abc` is not a valid user ID",
+                "Line 3: `-4` is not a valid user ID",
+                "Line 4: `-1` is not a valid amount of XP",
+                "Line 6: user 5 is in the file more than once",
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_header_only_counts_on_the_first_line() {
+        let rows = parse_csv("1,1\nid,xp\n");
+        assert_eq!(rows.ids, [1]);
+        assert_eq!(rows.rejected, ["Line 2: `id` is not a valid user ID"]);
+    }
+
+    #[test]
+    fn json_string_and_number_fields() {
+        let value = serde_json::json!([
+            {"id": "123", "xp": 456},
+            {"id": 789, "xp": "10", "level": 1},
+        ]);
+        let rows = parse_json(value).expect("an array should parse");
+        assert_eq!(rows.ids, [123, 789]);
+        assert_eq!(rows.xps, [456, 10]);
+        assert!(rows.rejected.is_empty());
+    }
+
+    #[test]
+    fn json_rejections() {
+        let value = serde_json::json!([
+            {"id": "1"},
+            {"id": true, "xp": 1},
+            {"id": "2", "xp": 1.5},
+            {"id": "3", "xp": 3},
+        ]);
+        let rows = parse_json(value).expect("an array should parse");
+        assert_eq!(rows.ids, [3]);
+        assert_eq!(
+            rows.rejected,
+            [
+                "Entry 1: needs both an id and xp",
+                "Entry 2: needs both an id and xp",
+                "Entry 3: `1.5` is not a valid amount of XP",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_must_be_an_array() {
+        assert!(parse_json(serde_json::json!({"id": "1", "xp": 1})).is_none());
+    }
+}
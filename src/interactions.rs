@@ -0,0 +1,210 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::post,
+    Json, Router,
+};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use twilight_http::request::Form;
+use twilight_model::{
+    application::interaction::{Interaction, InteractionType},
+    http::interaction::{InteractionResponse, InteractionResponseType},
+};
+
+use crate::AppState;
+
+#[derive(Clone)]
+struct EndpointState {
+    app: AppState,
+    key: VerifyingKey,
+}
+
+/// Lets discord send interactions over HTTP instead of through the gateway. Set the app's
+/// Interactions Endpoint URL to `/interactions` on the HTTP server to use it.
+pub fn router(app: AppState, public_key: &str) -> Router {
+    let key: [u8; 32] = hex::decode(public_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .expect("DISCORD_PUBKEY should be the 64 hex characters of the app's public key");
+    let key = VerifyingKey::from_bytes(&key).expect("DISCORD_PUBKEY is not a valid public key");
+    Router::new()
+        .route("/interactions", post(interaction))
+        .with_state(EndpointState { app, key })
+}
+
+async fn interaction(
+    State(state): State<EndpointState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, StatusCode> {
+    // Discord checks that we turn away bad signatures, so this has to come before anything else.
+    if !verify(&state.key, &headers, &body) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let interaction: Interaction =
+        serde_json::from_slice(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+    // Pings don't come from anyone, so they'd never make it through dispatch.
+    if interaction.kind == InteractionType::Ping {
+        return Ok(Json(InteractionResponse {
+            kind: InteractionResponseType::Pong,
+            data: None,
+        })
+        .into_response());
+    }
+    reply(&crate::handler::respond(interaction, state.app).await)
+}
+
+// Attachment contents aren't part of the JSON, so a response with files has to be a form
+// with the JSON in payload_json, the same way twilight-http sends them over REST.
+fn reply(response: &InteractionResponse) -> Result<Response, StatusCode> {
+    let attachments = response
+        .data
+        .as_ref()
+        .and_then(|data| data.attachments.as_ref())
+        .filter(|attachments| !attachments.is_empty());
+    let Some(attachments) = attachments else {
+        return Ok(Json(response).into_response());
+    };
+    let payload = serde_json::to_vec(response).map_err(|e| {
+        warn!("Failed to serialize interaction response: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let mut form = Form::new().json_part(b"payload_json", &payload);
+    for attachment in attachments {
+        form = form.file_part(
+            format!("files[{}]", attachment.id).as_bytes(),
+            attachment.filename.as_bytes(),
+            &attachment.file,
+        );
+    }
+    let content_type = HeaderValue::from_bytes(&form.content_type())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(([(CONTENT_TYPE, content_type)], form.build()).into_response())
+}
+
+// The signature covers the timestamp header with the body straight after it.
+fn verify(key: &VerifyingKey, headers: &HeaderMap, body: &[u8]) -> bool {
+    let signature = headers
+        .get("X-Signature-Ed25519")
+        .and_then(|v| hex::decode(v.as_bytes()).ok())
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes));
+    let timestamp = headers.get("X-Signature-Timestamp");
+    let (Some(signature), Some(timestamp)) = (signature, timestamp) else {
+        return false;
+    };
+    let mut message = Vec::with_capacity(timestamp.len() + body.len());
+    message.extend_from_slice(timestamp.as_bytes());
+    message.extend_from_slice(body);
+    key.verify(&message, &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::HttpBody;
+    use ed25519_dalek::{Signer, SigningKey};
+    use twilight_model::http::attachment::Attachment;
+    use twilight_util::builder::InteractionResponseDataBuilder;
+
+    const BODY: &[u8] = br#"{"type":1}"#;
+
+    fn signed(key: &SigningKey, timestamp: &str, body: &[u8]) -> HeaderMap {
+        let mut message = timestamp.as_bytes().to_vec();
+        message.extend_from_slice(body);
+        let signature = key.sign(&message);
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "X-Signature-Ed25519",
+            HeaderValue::from_str(&hex::encode(signature.to_bytes())).unwrap(),
+        );
+        headers.insert(
+            "X-Signature-Timestamp",
+            HeaderValue::from_str(timestamp).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn good_signatures_pass() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let headers = signed(&key, "1690000000", BODY);
+        assert!(verify(&key.verifying_key(), &headers, BODY));
+    }
+
+    #[test]
+    fn tampering_fails() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let headers = signed(&key, "1690000000", BODY);
+        assert!(!verify(&key.verifying_key(), &headers, br#"{"type":2}"#));
+        // The timestamp is signed too, so replaying with a new one doesn't work
+        let mut replayed = headers.clone();
+        replayed.insert(
+            "X-Signature-Timestamp",
+            HeaderValue::from_static("1690000001"),
+        );
+        assert!(!verify(&key.verifying_key(), &replayed, BODY));
+        let other = SigningKey::from_bytes(&[8; 32]);
+        assert!(!verify(&other.verifying_key(), &headers, BODY));
+    }
+
+    #[test]
+    fn missing_or_mangled_headers_fail() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        assert!(!verify(&key.verifying_key(), &HeaderMap::new(), BODY));
+        let mut headers = signed(&key, "1690000000", BODY);
+        headers.insert("X-Signature-Ed25519", HeaderValue::from_static("zz"));
+        assert!(!verify(&key.verifying_key(), &headers, BODY));
+    }
+
+    async fn read(response: Response) -> (String, Vec<u8>) {
+        let content_type = response.headers()[CONTENT_TYPE]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        (content_type, bytes)
+    }
+
+    #[tokio::test]
+    async fn plain_replies_are_json() {
+        let response = InteractionResponse {
+            kind: InteractionResponseType::ChannelMessageWithSource,
+            data: Some(InteractionResponseDataBuilder::new().content("hi").build()),
+        };
+        let (content_type, body) = read(reply(&response).unwrap()).await;
+        assert_eq!(content_type, "application/json");
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["data"]["content"], "hi");
+    }
+
+    #[tokio::test]
+    async fn attachments_are_sent_as_a_form() {
+        let card = Attachment {
+            description: None,
+            file: b"not really a png".to_vec(),
+            filename: "card.png".to_string(),
+            id: 0,
+        };
+        let response = InteractionResponse {
+            kind: InteractionResponseType::ChannelMessageWithSource,
+            data: Some(
+                InteractionResponseDataBuilder::new()
+                    .attachments([card])
+                    .build(),
+            ),
+        };
+        let (content_type, body) = read(reply(&response).unwrap()).await;
+        assert!(content_type.starts_with("multipart/form-data; boundary="));
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains(r#"name="payload_json""#));
+        assert!(body.contains(r#"name="files[0]"; filename="card.png""#));
+        assert!(body.contains("not really a png"));
+    }
+}
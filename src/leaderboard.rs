@@ -1,6 +1,7 @@
-use crate::{cmd_defs::LeaderboardCommand, AppState, Error};
+use crate::{cmd_defs::LeaderboardCommand, events::XpSource, AppState, Error};
 
 use std::fmt::Write;
+use twilight_interactions::command::{CommandOption, CreateOption};
 use twilight_model::{
     application::interaction::{
         message_component::MessageComponentInteractionData, modal::ModalInteractionData,
@@ -9,77 +10,250 @@ use twilight_model::{
         component::{ActionRow, Button, ButtonStyle, TextInput, TextInputStyle},
         Component, ReactionType,
     },
-    http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+    http::{
+        attachment::Attachment,
+        interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+    },
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{GuildMarker, RoleMarker, UserMarker},
         Id,
     },
 };
 use twilight_util::builder::{
-    embed::{EmbedBuilder, EmbedFooterBuilder},
+    embed::{EmbedBuilder, EmbedFooterBuilder, ImageSource},
     InteractionResponseDataBuilder,
 };
 
+// Custom IDs can only be 100 characters, and this shares them with a season name and a role ID.
+const JUMP_TARGET: &str = "jump";
+const ME_TARGET: &str = "me";
+// Rows are numbered from the page, so this is as far as pages can go without overflowing.
+const MAX_ZPAGE: i64 = i64::MAX / 10 - 1;
+// Discord's user IDs all have at least 17 digits, and no leaderboard has anywhere near this many pages.
+const MIN_USER_ID: i64 = 10_000_000_000_000_000;
+
+/// Which XP counts towards a leaderboard. Anything other than all-time ranks people by
+/// the XP they earned since the window started, summed from `xp_events`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, CreateOption, CommandOption)]
+pub enum Period {
+    #[default]
+    #[option(name = "All time", value = "all")]
+    AllTime,
+    #[option(name = "This month", value = "month")]
+    Month,
+    #[option(name = "This week", value = "week")]
+    Week,
+    #[option(name = "Last 24 hours", value = "day")]
+    Day,
+}
+
+impl Period {
+    // Anything we don't recognize is all-time, which is what buttons from before periods meant.
+    fn from_value(value: &str) -> Self {
+        match value {
+            "month" => Self::Month,
+            "week" => Self::Week,
+            "day" => Self::Day,
+            _ => Self::AllTime,
+        }
+    }
+    const fn label(self) -> &'static str {
+        match self {
+            Self::AllTime => "All time",
+            Self::Month => "This month",
+            Self::Week => "This week",
+            Self::Day => "Last 24 hours",
+        }
+    }
+}
+
+/// Everything a leaderboard message shows besides the page. This rides along in the custom IDs
+/// of the buttons and the go-to-page modal, like `3:week`, `3:all,img,r1234` or
+/// `3:all:Summer 2023`, so paging around keeps showing the same board without us storing anything.
+#[derive(Clone, Debug, Default)]
+struct View {
+    period: Period,
+    season: Option<String>,
+    image: bool,
+    role: Option<Id<RoleMarker>>,
+}
+
+impl View {
+    fn custom_id(&self, target: impl std::fmt::Display) -> String {
+        let mut custom_id = format!("{target}:{}", self.period.value());
+        if self.image {
+            custom_id += ",img";
+        }
+        if let Some(role) = self.role {
+            write!(custom_id, ",r{role}").ok();
+        }
+        if let Some(season) = &self.season {
+            custom_id.push(':');
+            custom_id += season;
+        }
+        custom_id
+    }
+    // Buttons from before any of this existed are just a page number, which means all-time.
+    fn parse(custom_id: &str) -> (&str, Self) {
+        let mut parts = custom_id.splitn(3, ':');
+        let target = parts.next().unwrap_or_default();
+        // The period can have flags after it, split off with commas.
+        let mut flags = parts.next().unwrap_or_default().split(',');
+        let period = flags.next().map(Period::from_value).unwrap_or_default();
+        let mut view = Self {
+            period,
+            ..Default::default()
+        };
+        for flag in flags {
+            if flag == "img" {
+                view.image = true;
+            } else if let Some(role) = flag.strip_prefix('r') {
+                view.role = role.parse().ok();
+            }
+        }
+        view.season = parts.next().map(ToString::to_string);
+        (target, view)
+    }
+    // The query parameter for the role filter, where NULL means everyone.
+    #[allow(clippy::cast_possible_wrap)]
+    fn role_param(&self) -> Option<i64> {
+        self.role.map(|role| role.get() as i64)
+    }
+    fn label(&self) -> &str {
+        self.season
+            .as_deref()
+            .unwrap_or_else(|| self.period.label())
+    }
+}
+
 pub async fn leaderboard(
     guild_id: Id<GuildMarker>,
+    token: String,
     state: AppState,
     prefs: LeaderboardCommand,
 ) -> Result<InteractionResponse, Error> {
     // "zpage" means "zero-indexed page", which is how this is represented internally.
     // We add one whenever we show it to the user, and add one every time we get it from the user.
+    if prefs.season.is_some() && prefs.period.is_some() {
+        return Err(Error::SeasonWithPeriod);
+    }
+    if let Some(season) = &prefs.season {
+        #[allow(clippy::cast_possible_wrap)]
+        let exists = query!(
+            "SELECT name FROM seasons WHERE guild = $1 AND name = $2",
+            guild_id.get() as i64,
+            season
+        )
+        .fetch_optional(&state.db)
+        .await?
+        .is_some();
+        if !exists {
+            return Err(Error::UnknownSeason(season.clone()));
+        }
+    }
+    let view = View {
+        period: prefs.period.unwrap_or_default(),
+        season: prefs.season,
+        image: prefs.image.unwrap_or(false),
+        // Everyone has the @everyone role, which has the same ID as the guild.
+        role: prefs.role.filter(|role| role.cast() != guild_id),
+    };
+    let highlight = prefs.user.map(|pick| pick.resolved.id);
     let zpage = if let Some(pick) = prefs.page {
-        pick - 1
-    } else if let Some(pick) = prefs.user {
-        get_user_position(pick.resolved.id, guild_id, &state.db).await?
+        zero_indexed(pick).ok_or(Error::NoUsersForPage)?
+    } else if let Some(user_id) = highlight {
+        get_user_position(user_id, guild_id, &view, &state.db).await?
     } else {
         0
     };
+    respond(guild_id, zpage, view, highlight, false, token, state).await
+}
+
+/// Answers with a page of the leaderboard, with `highlight`'s entry picked out if they're on it.
+/// `update` is whether this replaces the leaderboard the buttons are on, instead of sending a new one.
+async fn respond(
+    guild_id: Id<GuildMarker>,
+    zpage: i64,
+    view: View,
+    highlight: Option<Id<UserMarker>>,
+    update: bool,
+    token: String,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    // Button IDs can be anything, so this can't trust the page to be sensible.
+    if !(0..=MAX_ZPAGE).contains(&zpage) {
+        return Err(Error::NoUsersForPage);
+    }
+    if view.image {
+        let kind = if update {
+            InteractionResponseType::DeferredUpdateMessage
+        } else {
+            InteractionResponseType::DeferredChannelMessageWithSource
+        };
+        return image_leaderboard(guild_id, zpage, view, highlight, token, state, kind).await;
+    }
+    let kind = if update {
+        InteractionResponseType::UpdateMessage
+    } else {
+        InteractionResponseType::ChannelMessageWithSource
+    };
     Ok(InteractionResponse {
-        data: Some(gen_leaderboard(guild_id, state.db, zpage).await?),
-        kind: InteractionResponseType::ChannelMessageWithSource,
+        kind,
+        data: Some(gen_leaderboard(guild_id, &state, zpage, &view, highlight).await?),
     })
 }
 
 async fn gen_leaderboard(
     guild_id: Id<GuildMarker>,
-    db: sqlx::PgPool,
+    state: &AppState,
     zpage: i64,
+    view: &View,
+    highlight: Option<Id<UserMarker>>,
 ) -> Result<InteractionResponseData, Error> {
-    #[allow(clippy::cast_possible_wrap)]
-    let users = query!(
-        "SELECT * FROM levels WHERE guild = $1 ORDER BY xp DESC LIMIT 10 OFFSET $2",
-        guild_id.get() as i64,
-        zpage * 10
-    )
-    .fetch_all(&db)
-    .await?;
+    let users = fetch_page(guild_id, state, zpage, view).await?;
     if users.is_empty() {
         return Err(Error::NoUsersForPage);
     }
     // this is kinda the only way to do this
     // It's designed to only allocate once, at the start here
     let mut description = String::with_capacity(users.len() * 128);
-    #[allow(clippy::cast_sign_loss)]
-    for (i, user) in users.iter().enumerate() {
-        let level = mee6::LevelInfo::new(user.xp as u64).level();
-        let rank: i64 = i as i64 + (zpage * 10) + 1;
-        writeln!(
-            description,
-            "**#{rank}.** <@{}> - Level {level}",
-            user.id as u64
-        )
-        .ok();
+    if let Some(role) = view.role {
+        writeln!(description, "Members with <@&{role}>\n").ok();
     }
-    if description.is_empty() {
-        description += "Nobody is ranked yet.";
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+    for (i, (id, xp)) in users.iter().enumerate() {
+        let rank: i64 = i as i64 + (zpage * 10) + 1;
+        // Levels only make sense for someone's whole XP, so windows show what they earned instead.
+        let score = if view.period == Period::AllTime {
+            format!("Level {}", mee6::LevelInfo::new(*xp as u64).level())
+        } else {
+            format!("{xp} XP")
+        };
+        if highlight.is_some_and(|user_id| user_id.get() == *id as u64) {
+            writeln!(
+                description,
+                "**__#{rank}. <@{}> - {score}__** ⬅️",
+                *id as u64
+            )
+            .ok();
+        } else {
+            writeln!(description, "**#{rank}.** <@{}> - {score}", *id as u64).ok();
+        }
     }
     let embed = EmbedBuilder::new()
         .description(description)
-        .footer(EmbedFooterBuilder::new(format!("Page {}", zpage + 1)).build())
+        .footer(EmbedFooterBuilder::new(format!("Page {} • {}", zpage + 1, view.label())).build())
         .color(crate::THEME_COLOR)
         .build();
+    Ok(InteractionResponseDataBuilder::new()
+        .components([page_buttons(zpage, users.len(), view)])
+        .embeds([embed])
+        .build())
+}
+
+fn page_buttons(zpage: i64, page_len: usize, view: &View) -> Component {
     let back_button = Component::Button(Button {
-        custom_id: Some((zpage - 1).to_string()),
+        custom_id: Some(view.custom_id(zpage - 1)),
         disabled: zpage == 0,
         emoji: Some(ReactionType::Unicode {
             name: "⬅".to_string(),
@@ -89,20 +263,20 @@ async fn gen_leaderboard(
         url: None,
     });
     let select_button = Component::Button(Button {
-        custom_id: Some("jump_modal".to_string()),
+        custom_id: Some(view.custom_id(JUMP_TARGET)),
         // this checks if we are on both the last page and the first page, in which case we do not need to be able to jump
-        disabled: users.len() < 10 && zpage == 0,
+        disabled: page_len < 10 && zpage == 0,
         emoji: None,
         label: Some("Go to page".to_string()),
         style: ButtonStyle::Primary,
         url: None,
     });
     let forward_button = Component::Button(Button {
-        custom_id: Some((zpage + 1).to_string()),
+        custom_id: Some(view.custom_id(zpage + 1)),
         // this checks if the users on the current page are less then 10.
         // If this is the case, that means we *must* be at the last page.
         // this saves us doing weird counting shenanigans with the db
-        disabled: users.len() < 10,
+        disabled: page_len < 10,
         emoji: Some(ReactionType::Unicode {
             name: "➡️".to_string(),
         }),
@@ -110,44 +284,245 @@ async fn gen_leaderboard(
         style: ButtonStyle::Primary,
         url: None,
     });
-    Ok(InteractionResponseDataBuilder::new()
-        .components([Component::ActionRow(ActionRow {
-            components: vec![back_button, select_button, forward_button],
-        })])
-        .embeds([embed])
-        .build())
+    let me_button = Component::Button(Button {
+        custom_id: Some(view.custom_id(ME_TARGET)),
+        disabled: false,
+        emoji: None,
+        label: Some("Me".to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    });
+    Component::ActionRow(ActionRow {
+        components: vec![back_button, select_button, forward_button, me_button],
+    })
+}
+
+/// Image leaderboards take too long to draw to answer straight away, so this says we'll
+/// answer later with `kind`, and then edits in the picture. The page is looked up first,
+/// so a page that doesn't exist gets a normal error instead of replacing the leaderboard.
+async fn image_leaderboard(
+    guild_id: Id<GuildMarker>,
+    zpage: i64,
+    view: View,
+    highlight: Option<Id<UserMarker>>,
+    token: String,
+    state: AppState,
+    kind: InteractionResponseType,
+) -> Result<InteractionResponse, Error> {
+    let users = fetch_page(guild_id, &state, zpage, &view).await?;
+    if users.is_empty() {
+        return Err(Error::NoUsersForPage);
+    }
+    tokio::spawn(async move {
+        let Err(err) = send_image(&users, zpage, &view, highlight, &token, &state).await else {
+            return;
+        };
+        // Once deferred, the first followup takes the deferred message's place whatever its
+        // flags say, so the error goes in the message itself, without the page buttons.
+        let embed = EmbedBuilder::new().description(err.to_string()).build();
+        let embeds = [embed];
+        match state
+            .client
+            .interaction(state.my_id)
+            .update_response(&token)
+            .embeds(Some(&embeds))
+            .and_then(|update| update.components(Some(&[])))
+        {
+            Ok(update) => {
+                if let Err(e) = update.keep_attachment_ids(&[]).await {
+                    warn!("{e:#?}");
+                }
+            }
+            Err(e) => warn!("{e:#?}"),
+        }
+    });
+    Ok(InteractionResponse { kind, data: None })
+}
+
+async fn send_image(
+    users: &[(i64, i64)],
+    zpage: i64,
+    view: &View,
+    highlight: Option<Id<UserMarker>>,
+    token: &str,
+    state: &AppState,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
+    let rows: Vec<crate::leaderboard_image::Row> = users
+        .iter()
+        .enumerate()
+        .map(|(i, (id, xp))| crate::leaderboard_image::Row {
+            rank: i as i64 + (zpage * 10) + 1,
+            user_id: Id::new(*id as u64),
+            xp: *xp,
+            highlight: highlight.is_some_and(|user_id| user_id.get() == *id as u64),
+        })
+        .collect();
+    let png =
+        crate::leaderboard_image::render(&rows, view.period == Period::AllTime, state).await?;
+    let image = Attachment {
+        description: Some(format!(
+            "Page {} of the leaderboard for {}.",
+            zpage + 1,
+            view.label()
+        )),
+        file: png,
+        filename: "leaderboard.png".to_string(),
+        id: 0,
+    };
+    let mut embed = EmbedBuilder::new();
+    if let Some(role) = view.role {
+        embed = embed.description(format!("Members with <@&{role}>"));
+    }
+    let embed = embed
+        .image(ImageSource::attachment("leaderboard.png")?)
+        .footer(EmbedFooterBuilder::new(format!("Page {} • {}", zpage + 1, view.label())).build())
+        .color(crate::THEME_COLOR)
+        .build();
+    let embeds = [embed];
+    let components = [page_buttons(zpage, users.len(), view)];
+    let attachments = [image];
+    // Uploading a new attachment drops the old page's one.
+    state
+        .client
+        .interaction(state.my_id)
+        .update_response(token)
+        .embeds(Some(&embeds))?
+        .components(Some(&components))?
+        .attachments(&attachments)?
+        .await?;
+    Ok(())
+}
+
+/// Returns the user IDs and XP on one page of the all-time leaderboard, best first.
+pub async fn all_time_page(
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+    zpage: i64,
+) -> Result<Vec<(i64, i64)>, Error> {
+    fetch_page(guild_id, state, zpage, &View::default()).await
+}
+
+// Returns the user IDs and XP on one page of the leaderboard, best first.
+async fn fetch_page(
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+    zpage: i64,
+    view: &View,
+) -> Result<Vec<(i64, i64)>, Error> {
+    let db = &state.db;
+    let _timer = state.metrics.query_timer("leaderboard_page");
+    #[allow(clippy::cast_possible_wrap)]
+    Ok(if let Some(season) = &view.season {
+        query!(
+            "SELECT id, xp FROM season_levels WHERE guild = $1 AND season = $2
+             AND ($4::BIGINT IS NULL OR id IN (SELECT id FROM member_roles WHERE guild = $1 AND role = $4))
+             ORDER BY xp DESC, id LIMIT 10 OFFSET $3",
+            guild_id.get() as i64,
+            season,
+            zpage * 10,
+            view.role_param()
+        )
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|v| (v.id, v.xp))
+        .collect()
+    } else if view.period == Period::AllTime {
+        query!(
+            "SELECT id, xp FROM levels WHERE guild = $1
+             AND ($3::BIGINT IS NULL OR id IN (SELECT id FROM member_roles WHERE guild = $1 AND role = $3))
+             ORDER BY xp DESC, id LIMIT 10 OFFSET $2",
+            guild_id.get() as i64,
+            zpage * 10,
+            view.role_param()
+        )
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|v| (v.id, v.xp))
+        .collect()
+    } else {
+        // Only XP earned by talking counts. Imports, /xp and seasons ending aren't earned in the window.
+        // People who lost XP in the window have nothing to show, so they're left off.
+        query!(
+            r#"WITH window_start AS (
+                SELECT CASE $2
+                    WHEN 'month' THEN date_trunc('month', now())
+                    WHEN 'week' THEN date_trunc('week', now())
+                    ELSE now() - interval '1 day'
+                END AS start
+            ), changes AS (
+                SELECT id, delta FROM xp_events
+                WHERE guild = $1 AND source = $5 AND created_at >= (SELECT start FROM window_start)
+                UNION ALL
+                SELECT id, delta FROM xp_event_summaries
+                WHERE guild = $1 AND source = $5 AND day >= (SELECT start FROM window_start)
+            )
+            SELECT id AS "id!", SUM(delta)::BIGINT AS "xp!" FROM changes
+            WHERE ($4::BIGINT IS NULL OR id IN (SELECT id FROM member_roles WHERE guild = $1 AND role = $4))
+            GROUP BY id HAVING SUM(delta) > 0
+            ORDER BY 2 DESC, id LIMIT 10 OFFSET $3"#,
+            guild_id.get() as i64,
+            view.period.value(),
+            zpage * 10,
+            view.role_param(),
+            XpSource::Message.as_str()
+        )
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|v| (v.id, v.xp))
+        .collect()
+    })
 }
 
 pub async fn process_modal_submit(
     data: ModalInteractionData,
     guild_id: Id<GuildMarker>,
+    token: String,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
-    let actions = data.components.get(0).ok_or(Error::NoModalActionRow)?;
-    let field = actions.components.get(0).ok_or(Error::NoFormField)?;
-    let offset: i64 = field
+    let (_, view) = View::parse(&data.custom_id);
+    let actions = data.components.first().ok_or(Error::NoModalActionRow)?;
+    let field = actions.components.first().ok_or(Error::NoFormField)?;
+    let destination = field
         .value
-        .as_ref()
+        .as_deref()
         .ok_or(Error::NoDestinationInComponent)?
-        .parse()?;
-    Ok(InteractionResponse {
-        kind: InteractionResponseType::UpdateMessage,
-        data: Some(gen_leaderboard(guild_id, state.db, offset).await?),
-    })
+        .trim();
+    // Small numbers are pages. Anything else, including user IDs, is someone to find.
+    if let Some(page) = destination
+        .parse::<i64>()
+        .ok()
+        .filter(|page| *page < MIN_USER_ID)
+    {
+        let zpage = zero_indexed(page).ok_or(Error::NoUsersForPage)?;
+        return respond(guild_id, zpage, view, None, true, token, state).await;
+    }
+    let user_id = find_member(guild_id, destination, &state).await?;
+    let zpage = locate(user_id, guild_id, &view, &state)
+        .await?
+        .ok_or_else(|| Error::UserNotOnLeaderboard(destination.to_string()))?;
+    respond(guild_id, zpage, view, Some(user_id), true, token, state).await
 }
 
 pub async fn process_message_component(
     data: MessageComponentInteractionData,
     guild_id: Id<GuildMarker>,
+    invoker_id: Id<UserMarker>,
+    token: String,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
-    if data.custom_id == "jump_modal" {
+    let (target, view) = View::parse(&data.custom_id);
+    // Buttons used to say jump_modal, but role filters and season names needed the room.
+    if target == JUMP_TARGET || target == "jump_modal" {
         let input = TextInput {
             custom_id: "jump_modal_input".to_string(),
             label: "jump_destination".to_string(),
-            max_length: Some(6),
+            max_length: Some(100),
             min_length: Some(1),
-            placeholder: Some("What page to jump to".to_string()),
+            placeholder: Some("A page number, or someone's name or mention".to_string()),
             required: Some(true),
             style: TextInputStyle::Short,
             value: None,
@@ -159,35 +534,248 @@ pub async fn process_message_component(
                     .components([Component::ActionRow(ActionRow {
                         components: vec![Component::TextInput(input)],
                     })])
-                    .custom_id("jump_modal")
+                    .custom_id(view.custom_id(JUMP_TARGET))
                     .title("Go to page..")
                     .build(),
             ),
         });
     }
+    if target == ME_TARGET {
+        let zpage = locate(invoker_id, guild_id, &view, &state)
+            .await?
+            .ok_or(Error::NotOnLeaderboard)?;
+        return respond(guild_id, zpage, view, Some(invoker_id), true, token, state).await;
+    }
     // when we create the buttons, we set next and previous's custom IDs to the current page
     // plus and minus 1. This means that we don't have to store which page which
     // message is on, because the component will tell us exactly where it wants to go!
-    let offset: i64 = data.custom_id.parse()?;
-    Ok(InteractionResponse {
-        kind: InteractionResponseType::UpdateMessage,
-        data: Some(gen_leaderboard(guild_id, state.db, offset).await?),
-    })
+    let offset: i64 = target.parse()?;
+    respond(guild_id, offset, view, None, true, token, state).await
+}
+
+/// Works out who someone typed into the go-to-page box, from a mention or a name.
+async fn find_member(
+    guild_id: Id<GuildMarker>,
+    text: &str,
+    state: &AppState,
+) -> Result<Id<UserMarker>, Error> {
+    let mentioned = text
+        .strip_prefix("<@")
+        .and_then(|rest| rest.strip_suffix('>'))
+        .map(|rest| rest.trim_start_matches('!'))
+        .and_then(|id| id.parse().ok());
+    if let Some(user_id) = mentioned.or_else(|| text.parse().ok()) {
+        return Ok(user_id);
+    }
+    // Discord searches usernames and nicknames, and gives back the best match first.
+    let name = text.trim_start_matches('@');
+    state
+        .client
+        .search_guild_members(guild_id, name)
+        .await?
+        .models()
+        .await?
+        .first()
+        .map(|member| member.user.id)
+        .ok_or_else(|| Error::NoSuchMember(name.to_string()))
+}
+
+/// Turns a page number someone typed into a zero-indexed page, if there could be such a page.
+pub fn zero_indexed(page: i64) -> Option<i64> {
+    page.checked_sub(1)
+        .filter(|zpage| (0..=MAX_ZPAGE).contains(zpage))
+}
+
+/// Finds which page someone is on, if they're on this leaderboard at all.
+async fn locate(
+    user_id: Id<UserMarker>,
+    guild_id: Id<GuildMarker>,
+    view: &View,
+    state: &AppState,
+) -> Result<Option<i64>, Error> {
+    let timer = state.metrics.query_timer("leaderboard_position");
+    let zpage = get_user_position(user_id, guild_id, view, &state.db).await?;
+    timer.observe_duration();
+    // Someone who isn't ranked counts as having nobody above them, so check they're really there.
+    #[allow(clippy::cast_possible_wrap)]
+    let on_page = fetch_page(guild_id, state, zpage, view)
+        .await?
+        .iter()
+        .any(|(id, _)| *id == user_id.get() as i64);
+    Ok(on_page.then_some(zpage))
 }
 
-// this is a really simple wrapper function
+// Returns the zero-indexed page someone is on. Ties are broken by ID, the same way pages are ordered.
 async fn get_user_position(
     user_id: Id<UserMarker>,
     guild_id: Id<GuildMarker>,
+    view: &View,
     db: &sqlx::PgPool,
 ) -> Result<i64, Error> {
+    if let Some(season) = &view.season {
+        #[allow(clippy::cast_possible_wrap)]
+        return Ok(query!(
+            "WITH me AS (SELECT xp FROM season_levels WHERE id = $1 AND guild = $2 AND season = $3)
+             SELECT COUNT(*) as count FROM season_levels WHERE guild = $2 AND season = $3
+             AND (xp > (SELECT xp FROM me) OR (xp = (SELECT xp FROM me) AND id < $1))
+             AND ($4::BIGINT IS NULL OR id IN (SELECT id FROM member_roles WHERE guild = $2 AND role = $4))",
+            user_id.get() as i64,
+            guild_id.get() as i64,
+            season,
+            view.role_param()
+        )
+        .fetch_one(db)
+        .await?
+        .count
+        .unwrap_or(0)
+            / 10);
+    }
+    if view.period != Period::AllTime {
+        #[allow(clippy::cast_possible_wrap)]
+        return Ok(query!(
+            r#"WITH window_start AS (
+                SELECT CASE $3
+                    WHEN 'month' THEN date_trunc('month', now())
+                    WHEN 'week' THEN date_trunc('week', now())
+                    ELSE now() - interval '1 day'
+                END AS start
+            ), changes AS (
+                SELECT id, delta FROM xp_events
+                WHERE guild = $2 AND source = $5 AND created_at >= (SELECT start FROM window_start)
+                UNION ALL
+                SELECT id, delta FROM xp_event_summaries
+                WHERE guild = $2 AND source = $5 AND day >= (SELECT start FROM window_start)
+            ), earned AS (
+                SELECT id, SUM(delta) AS xp FROM changes
+                WHERE ($4::BIGINT IS NULL OR id IN (SELECT id FROM member_roles WHERE guild = $2 AND role = $4))
+                GROUP BY id HAVING SUM(delta) > 0
+            )
+            SELECT COUNT(*) AS count FROM earned
+            WHERE xp > (SELECT xp FROM earned WHERE id = $1)
+               OR (xp = (SELECT xp FROM earned WHERE id = $1) AND id < $1)"#,
+            user_id.get() as i64,
+            guild_id.get() as i64,
+            view.period.value(),
+            view.role_param(),
+            XpSource::Message.as_str()
+        )
+        .fetch_one(db)
+        .await?
+        .count
+        .unwrap_or(0)
+            / 10);
+    }
     #[allow(clippy::cast_possible_wrap)]
     Ok(query!(
-        "SELECT COUNT(*) as count FROM levels WHERE xp > (SELECT xp FROM levels WHERE id = $1 AND guild = $2) AND guild = $2",
+        "WITH me AS (SELECT xp FROM levels WHERE id = $1 AND guild = $2)
+         SELECT COUNT(*) as count FROM levels WHERE guild = $2
+         AND (xp > (SELECT xp FROM me) OR (xp = (SELECT xp FROM me) AND id < $1))
+         AND ($3::BIGINT IS NULL OR id IN (SELECT id FROM member_roles WHERE guild = $2 AND role = $3))",
         user_id.get() as i64,
-        guild_id.get() as i64
+        guild_id.get() as i64,
+        view.role_param()
     )
     .fetch_one(db)
     .await?
     .count.unwrap_or(0) / 10)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_round_trips() {
+        let view = View {
+            period: Period::Week,
+            ..View::default()
+        };
+        let custom_id = view.custom_id(3);
+        assert_eq!(custom_id, "3:week");
+        let (target, parsed) = View::parse(&custom_id);
+        assert_eq!(target, "3");
+        assert_eq!(parsed.period, Period::Week);
+        assert_eq!(parsed.season, None);
+    }
+
+    #[test]
+    fn old_buttons_are_all_time() {
+        let (target, view) = View::parse("7");
+        assert_eq!(target, "7");
+        assert_eq!(view.period, Period::AllTime);
+        assert_eq!(View::parse("7:nonsense").1.period, Period::AllTime);
+    }
+
+    #[test]
+    fn season_names_can_contain_colons() {
+        let view = View {
+            season: Some("Summer: the sequel".to_string()),
+            ..View::default()
+        };
+        let custom_id = view.custom_id(JUMP_TARGET);
+        assert_eq!(custom_id, "jump:all:Summer: the sequel");
+        let (target, parsed) = View::parse(&custom_id);
+        assert_eq!(target, JUMP_TARGET);
+        assert_eq!(parsed.season.as_deref(), Some("Summer: the sequel"));
+        assert_eq!(parsed.label(), "Summer: the sequel");
+    }
+
+    #[test]
+    fn image_flag_round_trips() {
+        let view = View {
+            image: true,
+            ..View::default()
+        };
+        let custom_id = view.custom_id(2);
+        assert_eq!(custom_id, "2:all,img");
+        assert!(View::parse(&custom_id).1.image);
+        assert!(!View::parse("2:all").1.image);
+    }
+
+    #[test]
+    fn role_flag_round_trips() {
+        let view = View {
+            period: Period::Month,
+            image: true,
+            role: Some(Id::new(1234)),
+            season: None,
+        };
+        let custom_id = view.custom_id(5);
+        assert_eq!(custom_id, "5:month,img,r1234");
+        let (_, parsed) = View::parse(&custom_id);
+        assert_eq!(parsed.period, Period::Month);
+        assert!(parsed.image);
+        assert_eq!(parsed.role, Some(Id::new(1234)));
+        // A mangled role is dropped rather than failing the whole button
+        assert_eq!(View::parse("5:all,rnope").1.role, None);
+        assert_eq!(View::parse("5:all,r0").1.role, None);
+    }
+
+    #[test]
+    fn longest_custom_id_fits() {
+        let view = View {
+            period: Period::Month,
+            image: true,
+            // Snowflakes fit in a BIGINT, so this is the longest a role ID gets
+            role: Some(Id::new(9_223_372_036_854_775_807)),
+            // Season names are capped at 64 characters by the commands
+            season: Some("x".repeat(64)),
+        };
+        assert!(view.custom_id(JUMP_TARGET).len() <= 100);
+    }
+
+    #[test]
+    fn typed_pages_are_bounded() {
+        assert_eq!(zero_indexed(1), Some(0));
+        assert_eq!(zero_indexed(12), Some(11));
+        assert_eq!(zero_indexed(0), None);
+        assert_eq!(zero_indexed(-3), None);
+        assert_eq!(zero_indexed(i64::MIN), None);
+        assert_eq!(zero_indexed(i64::MAX), None);
+        let last = zero_indexed(MAX_ZPAGE + 1).unwrap();
+        assert!(last
+            .checked_mul(10)
+            .and_then(|v| v.checked_add(10))
+            .is_some());
+    }
+}
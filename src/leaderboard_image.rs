@@ -0,0 +1,141 @@
+use std::fmt::Write;
+
+use twilight_model::id::{marker::UserMarker, Id};
+
+use crate::{history::escape, AppState, Error};
+
+const WIDTH: f64 = 1000.0;
+const ROW_HEIGHT: f64 = 80.0;
+const PADDING: f64 = 12.0;
+const BAR_LEFT: f64 = 190.0;
+const BAR_WIDTH: f64 = 560.0;
+const BAR_COLOR: &str = "#7289da";
+const ROW_COLOR: &str = "#2b2d31";
+const HIGHLIGHT_COLOR: &str = "#3c4270";
+// Long names would run into the level text
+const MAX_NAME_CHARS: usize = 28;
+
+/// One person on a leaderboard page.
+pub struct Row {
+    pub rank: i64,
+    pub user_id: Id<UserMarker>,
+    pub xp: i64,
+    pub highlight: bool,
+}
+
+/// Draws a page of the leaderboard as a PNG. With `show_levels` off, bars are measured
+/// against the top XP on the page, since a window's XP doesn't make a level.
+pub async fn render(rows: &[Row], show_levels: bool, state: &AppState) -> Result<Vec<u8>, Error> {
+    // Looking people up is most of the wait, so it all happens at once.
+    let lookups: Vec<_> = rows
+        .iter()
+        .map(|row| tokio::spawn(lookup(row.user_id, state.clone())))
+        .collect();
+    let mut people = Vec::with_capacity(rows.len());
+    for lookup in lookups {
+        people.push(lookup.await?);
+    }
+    state
+        .charts
+        .render(page_svg(rows, people, show_levels))
+        .await
+}
+
+// `people` is the name and avatar for each row.
+fn page_svg(rows: &[Row], people: Vec<(String, Option<String>)>, show_levels: bool) -> String {
+    let top_xp = rows.iter().map(|row| row.xp).max().unwrap_or(0).max(1);
+    #[allow(clippy::cast_precision_loss)]
+    let height = (rows.len() as f64).mul_add(ROW_HEIGHT, PADDING * 2.0);
+    let mut svg = String::with_capacity(rows.len() * 8192 + 512);
+    write!(
+        svg,
+        r##"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{WIDTH}" height="{height}" viewBox="0 0 {WIDTH} {height}">
+<rect width="100%" height="100%" rx="16" fill="#1e1f22"/>
+"##
+    )
+    .ok();
+    for (i, (row, (name, avatar))) in rows.iter().zip(people).enumerate() {
+        #[allow(clippy::cast_precision_loss)]
+        let top = (i as f64).mul_add(ROW_HEIGHT, PADDING);
+        #[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+        let (progress, right, below) = if show_levels {
+            let info = mee6::LevelInfo::new(row.xp as u64);
+            (
+                info.percentage(),
+                format!("Level {}", info.level()),
+                format!(
+                    "{} / {} XP",
+                    row.xp,
+                    mee6::xp_needed_for_level(info.level() + 1)
+                ),
+            )
+        } else {
+            (
+                row.xp as f64 / top_xp as f64,
+                format!("{} XP", row.xp),
+                String::new(),
+            )
+        };
+        let name: String = name.chars().take(MAX_NAME_CHARS).collect();
+        let fill = if row.highlight {
+            HIGHLIGHT_COLOR
+        } else {
+            ROW_COLOR
+        };
+        write!(
+            svg,
+            r##"<rect x="{PADDING}" y="{}" width="{}" height="{}" rx="12" fill="{fill}"/>
+<text x="70" y="{}" font-size="26" fill="#ffffff" text-anchor="middle">#{}</text>
+<clipPath id="avatar{i}"><circle cx="145" cy="{}" r="28"/></clipPath>
+<circle cx="145" cy="{}" r="28" fill="#3f4147"/>
+"##,
+            top + 4.0,
+            PADDING.mul_add(-2.0, WIDTH),
+            ROW_HEIGHT - 8.0,
+            top + 49.0,
+            row.rank,
+            top + 40.0,
+            top + 40.0,
+        )
+        .ok();
+        if let Some(avatar) = avatar {
+            writeln!(
+                svg,
+                r#"<image xlink:href="{avatar}" x="117" y="{}" width="56" height="56" clip-path="url(#avatar{i})"/>"#,
+                top + 12.0
+            )
+            .ok();
+        }
+        write!(
+            svg,
+            r##"<text x="{BAR_LEFT}" y="{}" font-size="22" fill="#ffffff">{}</text>
+<rect x="{BAR_LEFT}" y="{}" width="{BAR_WIDTH}" height="12" rx="6" fill="#3f4147"/>
+<rect x="{BAR_LEFT}" y="{}" width="{}" height="12" rx="6" fill="{BAR_COLOR}"/>
+<text x="{}" y="{}" font-size="22" fill="#ffffff" text-anchor="end">{right}</text>
+<text x="{}" y="{}" font-size="16" fill="#b5bac1" text-anchor="end">{below}</text>
+"##,
+            top + 34.0,
+            escape(&name),
+            top + 48.0,
+            top + 48.0,
+            BAR_WIDTH * progress.clamp(0.0, 1.0),
+            WIDTH - PADDING - 20.0,
+            top + 34.0,
+            WIDTH - PADDING - 20.0,
+            top + 62.0,
+        )
+        .ok();
+    }
+    svg += "</svg>";
+    svg
+}
+
+// People who deleted their account, or that discord won't tell us about, still hold their
+// spot. They just don't get a name or a picture.
+async fn lookup(user_id: Id<UserMarker>, state: AppState) -> (String, Option<String>) {
+    let Some(user) = state.users.get(user_id, &state.client).await else {
+        return ("Unknown user".to_string(), None);
+    };
+    let avatar = crate::levels::get_avatar(&state, &user).await.ok();
+    (user.name, avatar)
+}
@@ -1,4 +1,4 @@
-use crate::{AppState, Error};
+use crate::{card::CardStyle, config::GuildConfig, multipliers::Boosts, AppState, Error};
 
 use base64::Engine;
 use twilight_model::{
@@ -6,7 +6,10 @@ use twilight_model::{
         attachment::Attachment,
         interaction::{InteractionResponse, InteractionResponseType},
     },
-    id::{marker::GuildMarker, Id},
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
     user::User,
 };
 use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
@@ -15,31 +18,11 @@ pub async fn get_level(
     guild_id: Id<GuildMarker>,
     user: User,
     invoker: User,
+    boosts: Option<Boosts>,
     token: String,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
-    #[allow(clippy::cast_possible_wrap)]
-    let guild_id = guild_id.get() as i64;
-    // Select current XP from the database, return 0 if there is no row
-    #[allow(clippy::cast_possible_wrap)]
-    let xp = query!(
-        "SELECT xp FROM levels WHERE id = $1 AND guild = $2",
-        user.id.get() as i64,
-        guild_id
-    )
-    .fetch_optional(&state.db)
-    .await?
-    .map_or(0, |v| v.xp);
-    let rank = query!(
-        "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2",
-        xp,
-        guild_id
-    )
-    .fetch_one(&state.db)
-    .await?
-    .count
-    .unwrap_or(0)
-        + 1;
+    let (xp, rank) = xp_and_rank(guild_id, user.id, &state).await?;
     #[allow(clippy::cast_sign_loss)]
     let level_info = mee6::LevelInfo::new(xp as u64);
     // I am really not a big fan of this. Too much nesting. However, as far as i can tell
@@ -50,7 +33,9 @@ pub async fn get_level(
         if xp == 0 {
             "You aren't ranked yet, because you haven't sent any messages!".to_string()
         } else {
-            return generate_level_response(state, token, user, level_info, rank).await;
+            return Ok(generate_level_response(
+                state, guild_id, token, user, level_info, rank, boosts,
+            ));
         }
     } else if xp == 0 {
         format!(
@@ -59,7 +44,9 @@ pub async fn get_level(
             user.discriminator()
         )
     } else {
-        return generate_level_response(state, token, user, level_info, rank).await;
+        return Ok(generate_level_response(
+            state, guild_id, token, user, level_info, rank, boosts,
+        ));
     };
     Ok(InteractionResponse {
         kind: InteractionResponseType::ChannelMessageWithSource,
@@ -71,15 +58,62 @@ pub async fn get_level(
     })
 }
 
-async fn generate_level_response(
+/// Returns how much XP someone has in a guild, and their rank there.
+pub async fn xp_and_rank(
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    state: &AppState,
+) -> Result<(i64, i64), Error> {
+    let _timer = state.metrics.query_timer("rank");
+    let db = &state.db;
+    #[allow(clippy::cast_possible_wrap)]
+    let guild_id = guild_id.get() as i64;
+    // Select current XP from the database, return 0 if there is no row
+    #[allow(clippy::cast_possible_wrap)]
+    let xp = query!(
+        "SELECT xp FROM levels WHERE id = $1 AND guild = $2",
+        user_id.get() as i64,
+        guild_id
+    )
+    .fetch_optional(db)
+    .await?
+    .map_or(0, |v| v.xp);
+    let rank = query!(
+        "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2",
+        xp,
+        guild_id
+    )
+    .fetch_one(db)
+    .await?
+    .count
+    .unwrap_or(0)
+        + 1;
+    Ok((xp, rank))
+}
+
+fn generate_level_response(
     state: AppState,
+    guild_id: Id<GuildMarker>,
     token: String,
     user: User,
     level_info: mee6::LevelInfo,
     rank: i64,
-) -> Result<InteractionResponse, Error> {
+    boosts: Option<Boosts>,
+) -> InteractionResponse {
     tokio::task::spawn(async move {
-        let Err(err) = add_card(state.clone(), &token, user, level_info, rank).await else { return; };
+        let Err(err) = add_card(
+            state.clone(),
+            guild_id,
+            &token,
+            user,
+            level_info,
+            rank,
+            boosts,
+        )
+        .await
+        else {
+            return;
+        };
         let interaction_client = state.client.interaction(state.my_id);
         let embed = EmbedBuilder::new().description(err.to_string()).build();
         let embeds = &[embed];
@@ -87,36 +121,85 @@ async fn generate_level_response(
             Ok(awaitable) => {
                 if let Err(e) = awaitable.await {
                     warn!("{e:#?}");
-                };
+                }
             }
             Err(e) => {
                 warn!("{e:#?}");
             }
         };
     });
-    Ok(InteractionResponse {
+    InteractionResponse {
         kind: InteractionResponseType::DeferredChannelMessageWithSource,
         data: None,
-    })
+    }
 }
 
 async fn add_card(
     state: AppState,
+    guild_id: Id<GuildMarker>,
     token: &str,
     user: User,
     level_info: mee6::LevelInfo,
     rank: i64,
+    boosts: Option<Boosts>,
 ) -> Result<(), Error> {
+    let config = state.configs.get(guild_id, &state.db).await?;
+    let style = state
+        .metrics
+        .timed("card_style", CardStyle::load(user.id, &state.db))
+        .await?
+        .themed(&config);
+    let png = render_card(&state, guild_id, &config, &user, level_info, rank, &style).await?;
+    let card = Attachment {
+        description: Some(format!(
+            "{}#{} is level {} (rank #{}), and is {}% of the way to level {}.",
+            user.name,
+            user.discriminator(),
+            level_info.level(),
+            rank,
+            (level_info.percentage() * 100.0).round(),
+            level_info.level() + 1
+        )),
+        file: png,
+        filename: "card.png".to_string(),
+        id: 0,
+    };
+    let content = boosts
+        .filter(|boosts| !boosts.is_empty())
+        .map(|boosts| boosts.to_string());
+    let attachments = [card];
     let interaction_client = state.client.interaction(state.my_id);
-    #[allow(clippy::cast_possible_wrap)]
-    let toy = query!(
-        "SELECT toy FROM card_toy WHERE id = $1",
-        user.id.get() as i64
+    let mut followup = interaction_client
+        .create_followup(token)
+        .attachments(&attachments)?;
+    if let Some(content) = &content {
+        followup = followup.content(content)?;
+    }
+    followup.await?;
+    Ok(())
+}
+
+/// Draws someone's rank card, returning the PNG.
+pub async fn render_card(
+    state: &AppState,
+    guild_id: Id<GuildMarker>,
+    config: &GuildConfig,
+    user: &User,
+    level_info: mee6::LevelInfo,
+    rank: i64,
+    style: &CardStyle,
+) -> Result<Vec<u8>, Error> {
+    // Records how long this took when it's dropped, whichever way we leave.
+    let _timer = state.metrics.card_render.start_timer();
+    let toy = crate::toy::resolve(
+        user.id,
+        guild_id,
+        level_info.level(),
+        &config.allowed_toys,
+        &state.db,
     )
-    .fetch_optional(&state.db)
-    .await?
-    .and_then(|v| xpd_rank_card::Toy::from_filename(&v.toy));
-    let avatar = get_avatar(&state, &user).await?;
+    .await?;
+    let avatar = get_avatar(state, user).await?;
     #[allow(
         clippy::cast_precision_loss,
         clippy::cast_sign_loss,
@@ -128,7 +211,7 @@ async fn add_card(
         Some(user.discriminator().to_string())
     };
     #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-    let png = state
+    Ok(state
         .svg
         .render(xpd_rank_card::Context {
             level: level_info.level(),
@@ -140,32 +223,13 @@ async fn add_card(
             needed: mee6::xp_needed_for_level(level_info.level() + 1),
             toy,
             avatar,
-            font: xpd_rank_card::Font::Mojang,
-            colors: xpd_rank_card::colors::Colors::default(),
+            font: style.font(),
+            colors: style.colors(),
         })
-        .await?;
-    let card = Attachment {
-        description: Some(format!(
-            "{}#{} is level {} (rank #{}), and is {}% of the way to level {}.",
-            user.name,
-            user.discriminator(),
-            level_info.level(),
-            rank,
-            (level_info.percentage() * 100.0).round(),
-            level_info.level() + 1
-        )),
-        file: png,
-        filename: "card.png".to_string(),
-        id: 0,
-    };
-    interaction_client
-        .create_followup(token)
-        .attachments(&[card])?
-        .await?;
-    Ok(())
+        .await?)
 }
 
-async fn get_avatar(state: &AppState, user: &User) -> Result<String, Error> {
+pub async fn get_avatar(state: &AppState, user: &User) -> Result<String, Error> {
     let url = user.avatar.map_or_else(
         || {
             format!(
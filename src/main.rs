@@ -1,20 +1,47 @@
 #![deny(clippy::all, clippy::pedantic, clippy::nursery)]
+// Duration::from_mins and friends would raise the Rust version we need for no real gain.
+#![allow(clippy::duration_suboptimal_units)]
 
+mod card;
 mod cmd_defs;
+mod config;
+mod cooldowns;
 mod dispatch;
+mod events;
+mod exclusions;
+mod export;
 mod handler;
+mod health;
+mod history;
+mod import;
+mod interactions;
 mod leaderboard;
+mod leaderboard_image;
 mod levels;
+mod members;
 mod message;
+mod metrics;
 mod minicache;
+mod multipliers;
+mod rewards;
+mod seasons;
 mod toy;
+mod users;
+mod web;
+mod xp;
 
 use sqlx::PgPool;
 use std::sync::{atomic::AtomicBool, Arc};
 use tokio::task::JoinSet;
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 use twilight_gateway::{CloseFrame, Config, Event, Intents, Shard};
-use twilight_model::id::{marker::ApplicationMarker, Id};
+use twilight_model::{
+    channel::message::{Embed, MessageFlags},
+    gateway::payload::outgoing::RequestGuildMembers,
+    http::interaction::{InteractionResponse, InteractionResponseType},
+    id::{marker::ApplicationMarker, Id},
+};
+use twilight_util::builder::InteractionResponseDataBuilder;
 use xpd_rank_card::SvgState;
 
 #[macro_use]
@@ -34,8 +61,6 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .with(tracing_subscriber::EnvFilter::from_env("LOG"))
         .init();
-    let token =
-        std::env::var("DISCORD_TOKEN").expect("Expected environment variable DISCORD_TOKEN");
     let database_url =
         std::env::var("DATABASE_URL").expect("Expected environment variable DATABASE_URL");
     info!("Connecting to database {database_url}");
@@ -46,6 +71,16 @@ async fn main() {
         .run(&db)
         .await
         .expect("Failed to run database migrations!");
+    let http = reqwest::Client::new();
+    let mee6_url: Arc<str> =
+        std::env::var("MEE6_API_URL").map_or_else(|_| import::MEE6_DEFAULT_URL.into(), Into::into);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        run_cli(&args, &db, &http, &mee6_url).await;
+        return;
+    }
+    let token =
+        std::env::var("DISCORD_TOKEN").expect("Expected environment variable DISCORD_TOKEN");
     let client = Arc::new(twilight_http::Client::new(token.clone()));
     info!("Creating commands...");
     let my_id = client
@@ -58,9 +93,17 @@ async fn main() {
         .id;
     cmd_defs::register(client.interaction(my_id)).await;
     let svg = SvgState::new();
-    // We only need GUILD_MESSAGES, and we only use the fact that a message has been created, we do not use message content
-    let config = Config::new(token, Intents::GUILD_MESSAGES);
-    let cooldowns = minicache::MessagingCache::new();
+    // We only use the fact that a message has been created, we do not use message content.
+    // GUILDS is there so we hear about channels moving between categories, and roles being deleted.
+    // GUILD_MEMBERS keeps track of who has which role, for leaderboards filtered by role.
+    let config = Config::new(
+        token,
+        Intents::GUILD_MESSAGES | Intents::GUILDS | Intents::GUILD_MEMBERS,
+    );
+    let cooldowns = cooldowns::from_env(&db).await;
+    let channel_parents = exclusions::ChannelParents::new();
+    let metrics = metrics::Metrics::new();
+    let configs = config::ConfigCache::new(metrics.clone());
     let shards: Vec<Shard> =
         twilight_gateway::stream::create_recommended(&client, config, |_, builder| builder.build())
             .await
@@ -68,16 +111,38 @@ async fn main() {
             .collect();
     let senders: Vec<twilight_gateway::MessageSender> =
         shards.iter().map(twilight_gateway::Shard::sender).collect();
-    let http = reqwest::Client::new();
     info!("Connecting to discord");
     let state = AppState {
         db,
         client,
         my_id,
         cooldowns,
+        configs,
+        channel_parents,
         svg,
+        charts: history::ChartRenderer::new(),
+        metrics,
+        users: users::UserCache::new(),
+        shard_health: health::ShardHealth::new(shards.len()),
         http,
+        mee6_url,
     };
+    let retention_days = std::env::var("XP_EVENT_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(events::DEFAULT_RETENTION_DAYS);
+    tokio::spawn(events::retention_task(state.db.clone(), retention_days));
+    tokio::spawn(seasons::rollover_task(state.db.clone()));
+    tokio::spawn(config::invalidation_task(
+        state.db.clone(),
+        state.configs.clone(),
+    ));
+    if let Ok(addr) = std::env::var("HTTP_ADDR") {
+        let addr: std::net::SocketAddr = addr
+            .parse()
+            .expect("HTTP_ADDR should be an address to listen on, like 0.0.0.0:8080");
+        tokio::spawn(serve_http(addr, http_routes(&state)));
+    }
     let should_shutdown = Arc::new(AtomicBool::new(false));
 
     let mut set = JoinSet::new();
@@ -103,12 +168,67 @@ async fn main() {
     info!("Done, see ya!");
 }
 
+// Everything the HTTP server can do. Each part is only there if it's been set up.
+fn http_routes(state: &AppState) -> axum::Router {
+    let mut router = web::router(state.clone())
+        .merge(metrics::router(state.metrics.clone()))
+        .merge(health::router(state.db.clone(), state.shard_health.clone()));
+    if let Ok(key) = std::env::var("DISCORD_PUBKEY") {
+        router = router.merge(interactions::router(state.clone(), &key));
+    }
+    router
+}
+
+async fn serve_http(addr: std::net::SocketAddr, router: axum::Router) {
+    info!("Listening for HTTP on {addr}");
+    if let Err(e) = axum::Server::bind(&addr)
+        .serve(router.into_make_service())
+        .await
+    {
+        error!("HTTP server stopped: {e}");
+    }
+}
+
+// Subcommands do one job against the database and exit, without ever connecting to Discord.
+async fn run_cli(args: &[String], db: &PgPool, http: &reqwest::Client, mee6_url: &str) {
+    match args[0].as_str() {
+        "import-mee6" => {
+            let guild_id = args
+                .get(1)
+                .and_then(|v| v.parse().ok())
+                .and_then(Id::new_checked)
+                .expect("Usage: minixpd import-mee6 <guild id>");
+            match import::import_mee6(guild_id, db, http, mee6_url).await {
+                Ok(count) => info!("Imported {count} users from MEE6"),
+                Err(e) => error!("Failed to import from MEE6: {e}"),
+            }
+        }
+        other => error!("Unknown subcommand {other}! Available subcommands: import-mee6"),
+    }
+}
+
 async fn event_loop(mut shard: Shard, should_shutdown: Arc<AtomicBool>, state: AppState) {
+    // Asking for members waits on the shard's command ratelimit, which a few hundred guilds
+    // at startup would blow through. Queueing through the sender lets the shard send them
+    // as it can, while this loop keeps polling for events and heartbeats.
+    let sender = shard.sender();
     loop {
         match shard.next_event().await {
             Ok(event) => {
+                if let Event::GatewayHello(hello) = &event {
+                    state
+                        .shard_health
+                        .heartbeat_interval(&shard, hello.heartbeat_interval);
+                }
                 let state = state.clone();
+                let sender = sender.clone();
                 tokio::spawn(async move {
+                    if let Event::GuildCreate(guild) = &event {
+                        let request = RequestGuildMembers::builder(guild.id).query("", None);
+                        if let Err(e) = sender.command(&request) {
+                            warn!("Failed to request members of {}: {e}", guild.id);
+                        }
+                    }
                     if let Err(e) = handle_event(event, state).await {
                         // this includes even user caused errors. User beware. Don't set up automatic emails or anything.
                         warn!("Handler error: {e}");
@@ -117,6 +237,8 @@ async fn event_loop(mut shard: Shard, should_shutdown: Arc<AtomicBool>, state: A
             }
             Err(e) => error!("Shard loop error: {e}"),
         }
+        state.metrics.shard(&shard);
+        state.shard_health.update(&shard);
         if should_shutdown.load(std::sync::atomic::Ordering::Relaxed) {
             // We're shutting down either way, errors don't matter.
             _ = shard.close(CloseFrame::NORMAL).await;
@@ -130,18 +252,59 @@ async fn handle_event(event: Event, state: AppState) -> Result<(), Error> {
     match event {
         Event::MessageCreate(msg) => message::save(*msg, state).await,
         Event::InteractionCreate(i) => Box::pin(handler::handle(i.0, state)).await,
+        Event::ChannelUpdate(c) => {
+            state.channel_parents.forget(c.id);
+            Ok(())
+        }
+        Event::ChannelDelete(c) => {
+            state.channel_parents.forget(c.id);
+            Ok(())
+        }
+        Event::ThreadDelete(t) => {
+            state.channel_parents.forget(t.id);
+            Ok(())
+        }
+        Event::MemberChunk(c) => members::save_members(c.guild_id, &c.members, &state.db).await,
+        Event::MemberAdd(m) => members::save_members(m.guild_id, &[m.member], &state.db).await,
+        Event::MemberUpdate(m) => {
+            members::save_roles(m.guild_id, &[(m.user.id, &m.roles)], &state.db).await
+        }
+        Event::MemberRemove(m) => members::forget_member(m.guild_id, m.user.id, &state.db).await,
+        Event::RoleDelete(r) => members::forget_role(r.guild_id, r.role_id, &state.db).await,
+        // Guilds go unavailable during outages, which doesn't mean we've left them.
+        Event::GuildDelete(g) if !g.unavailable => members::forget_guild(g.id, &state.db).await,
         _ => Ok(()),
     }
 }
 
+#[must_use]
+pub fn ephemeral_embed_response(embed: Embed) -> InteractionResponse {
+    InteractionResponse {
+        kind: InteractionResponseType::ChannelMessageWithSource,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .embeds([embed])
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+        ),
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: PgPool,
     pub client: Arc<twilight_http::Client>,
     pub my_id: Id<ApplicationMarker>,
-    pub cooldowns: minicache::MessagingCache,
+    pub cooldowns: Arc<dyn cooldowns::CooldownBackend>,
+    pub configs: config::ConfigCache,
+    pub channel_parents: exclusions::ChannelParents,
     pub svg: SvgState,
+    pub charts: history::ChartRenderer,
+    pub metrics: metrics::Metrics,
+    pub users: users::UserCache,
+    pub shard_health: health::ShardHealth,
     pub http: reqwest::Client,
+    pub mee6_url: Arc<str>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -162,6 +325,8 @@ pub enum Error {
     NoInteractionData,
     #[error("Discord did not send a guild ID!")]
     NoGuildId,
+    #[error("That would give them more XP than can be stored!")]
+    TooMuchXp,
     #[error("This page does not exist!")]
     NoUsersForPage,
     #[error("This modal did not contain any action rows!")]
@@ -170,6 +335,52 @@ pub enum Error {
     NoFormField,
     #[error("This modal did not contain the required form data!")]
     NoDestinationInComponent,
+    #[error("You need to specify either a level or a role!")]
+    WrongArgumentCount,
+    #[error("Everyone already has @everyone, so it can't be a reward!")]
+    EveryoneReward,
+    #[error("That role is managed by an integration, so it can't be a reward!")]
+    ManagedRoleReward,
+    #[error("That role isn't below my highest role, so I can't give it out!")]
+    RoleTooHigh,
+    #[error("Bots aren't ranked, that would be silly!")]
+    BotsNotRanked,
+    #[error("You aren't on this leaderboard yet!")]
+    NotOnLeaderboard,
+    #[error("{0} isn't on this leaderboard yet!")]
+    UserNotOnLeaderboard(String),
+    #[error("Couldn't find anyone called {0} in this server!")]
+    NoSuchMember(String),
+    #[error("The minimum XP per message can't be more than the maximum!")]
+    InvertedXpRange,
+    #[error("You need to pick a channel to announce level-ups in!")]
+    NoLevelUpChannel,
+    #[error("You need to specify either a channel or a role!")]
+    NoChannelOrRole,
+    #[error("This server's MEE6 leaderboard doesn't exist or isn't public!")]
+    Mee6LeaderboardUnavailable,
+    #[error("MEE6 sent a user ID that isn't valid: {0}!")]
+    InvalidMee6UserId(String),
+    #[error("That file is too big to import!")]
+    ImportFileTooLarge,
+    #[error("JSON imports need to be a list of objects with an id and xp!")]
+    ImportNotJsonArray,
+    #[error("Adding that would give user {0} more XP than can be stored!")]
+    ImportTooMuchXp(i64),
+    #[error("{0} isn't a hex color! Try something like #1E1F22.")]
+    InvalidColor(String),
+    #[error("{0} on {1} is too hard to see ({2:.1}:1 contrast, needs at least {3}:1)!")]
+    LowContrast(String, String, f64, f64),
+    #[error("This server's admins have picked a card theme for everyone!")]
+    CardThemeForced,
+    #[error("There is no toy called {0}!")]
+    UnknownToy(String),
+    #[error("There is already a season called {0}!")]
+    SeasonExists(String),
+    #[error("There is no season called {0}!")]
+    UnknownSeason(String),
+    #[error("Past seasons can't be narrowed down to a period!")]
+    SeasonWithPeriod,
     #[error("Discord sent unknown custom button ID!")]
     InvalidCustomButtonId,
     #[error("Failed to parse custom ID as integer: {0}!")]
@@ -182,10 +393,24 @@ pub enum Error {
     ImageSource(#[from] twilight_util::builder::embed::image_source::ImageSourceAttachmentError),
     #[error("SVG renderer encountered an error: {0}!")]
     ImageGenerator(#[from] xpd_rank_card::Error),
+    #[error("Failed to process JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Failed to build chart: {0}")]
+    ChartSvg(#[from] resvg::usvg::Error),
+    #[error("Failed to encode chart: {0}")]
+    ChartPng(#[from] png::EncodingError),
+    #[error("Failed to allocate an image for the chart!")]
+    ChartPixmap,
+    #[error("Chart renderer stopped unexpectedly: {0}")]
+    ChartTask(#[from] tokio::task::JoinError),
+    #[error("Redis encountered an error: {0}")]
+    Redis(#[from] redis::RedisError),
     #[error("SQLx encountered an error: {0}")]
     Sqlx(#[from] sqlx::Error),
     #[error("Twilight-HTTP encountered an error: {0}")]
     TwilightHttp(#[from] twilight_http::Error),
+    #[error("Twilight-HTTP could not deserialize a response: {0}")]
+    DeserializeBody(#[from] twilight_http::response::DeserializeBodyError),
     #[error("Reqwest encountered an error: {0}")]
     ReqwestHttp(#[from] reqwest::Error),
 }
@@ -0,0 +1,107 @@
+use twilight_model::{
+    guild::Member,
+    id::{
+        marker::{GuildMarker, RoleMarker, UserMarker},
+        Id,
+    },
+};
+
+use crate::Error;
+
+/// Replaces the roles we know about for some members of a guild, all at once.
+/// Chunks can hold a thousand members, so this is two queries however many there are.
+pub async fn save_roles(
+    guild_id: Id<GuildMarker>,
+    members: &[(Id<UserMarker>, &[Id<RoleMarker>])],
+    db: &sqlx::PgPool,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let ids: Vec<i64> = members.iter().map(|(id, _)| id.get() as i64).collect();
+    // Postgres wants two lists of the same length, so every role gets its member's ID beside it.
+    let mut owners = Vec::with_capacity(members.len());
+    let mut roles = Vec::with_capacity(members.len());
+    #[allow(clippy::cast_possible_wrap)]
+    for (id, member_roles) in members {
+        for role in *member_roles {
+            owners.push(id.get() as i64);
+            roles.push(role.get() as i64);
+        }
+    }
+    #[allow(clippy::cast_possible_wrap)]
+    let guild = guild_id.get() as i64;
+    let mut txn = db.begin().await?;
+    query!(
+        "DELETE FROM member_roles WHERE guild = $1 AND id = ANY($2)",
+        guild,
+        &ids
+    )
+    .execute(&mut txn)
+    .await?;
+    query!(
+        "INSERT INTO member_roles (guild, id, role)
+         SELECT $1, id, role FROM UNNEST($2::BIGINT[], $3::BIGINT[]) AS roles (id, role)
+         ON CONFLICT DO NOTHING",
+        guild,
+        &owners,
+        &roles
+    )
+    .execute(&mut txn)
+    .await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+pub async fn save_members(
+    guild_id: Id<GuildMarker>,
+    members: &[Member],
+    db: &sqlx::PgPool,
+) -> Result<(), Error> {
+    let members: Vec<(Id<UserMarker>, &[Id<RoleMarker>])> = members
+        .iter()
+        .map(|member| (member.user.id, member.roles.as_slice()))
+        .collect();
+    save_roles(guild_id, &members, db).await
+}
+
+pub async fn forget_member(
+    guild_id: Id<GuildMarker>,
+    user_id: Id<UserMarker>,
+    db: &sqlx::PgPool,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "DELETE FROM member_roles WHERE guild = $1 AND id = $2",
+        guild_id.get() as i64,
+        user_id.get() as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn forget_role(
+    guild_id: Id<GuildMarker>,
+    role_id: Id<RoleMarker>,
+    db: &sqlx::PgPool,
+) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "DELETE FROM member_roles WHERE guild = $1 AND role = $2",
+        guild_id.get() as i64,
+        role_id.get() as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn forget_guild(guild_id: Id<GuildMarker>, db: &sqlx::PgPool) -> Result<(), Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "DELETE FROM member_roles WHERE guild = $1",
+        guild_id.get() as i64
+    )
+    .execute(db)
+    .await?;
+    Ok(())
+}
@@ -1,33 +1,83 @@
 use rand::Rng;
 use twilight_model::{
+    channel::message::AllowedMentions,
     gateway::payload::incoming::MessageCreate,
-    id::{marker::RoleMarker, Id},
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker, UserMarker},
+        Id,
+    },
 };
 
-use crate::AppState;
+use crate::{
+    config::{GuildConfig, LevelUpMode},
+    events::XpSource,
+    AppState,
+};
 
 pub async fn save(msg: MessageCreate, state: AppState) -> Result<(), crate::Error> {
-    let Some(guild_id) = msg.guild_id else { return Ok(()); };
+    let Some(guild_id) = msg.guild_id else {
+        return Ok(());
+    };
     // We ignore cooldown users and bots
-    if msg.author.bot || state.cooldowns.contains(guild_id, msg.author.id) {
+    if msg.author.bot {
+        return Ok(());
+    }
+    state.metrics.messages.inc();
+    let config = state.configs.get(guild_id, &state.db).await?;
+    if crate::exclusions::is_excluded(&msg, &config, &state).await? {
+        return Ok(());
+    }
+    // Only whoever starts the cooldown earns XP, so messages sent together can't all count.
+    let started = state
+        .metrics
+        .timed(
+            "cooldown",
+            state
+                .cooldowns
+                .try_start(guild_id, msg.author.id, config.cooldown),
+        )
+        .await?;
+    if !started {
+        state.metrics.cooldown_hits.inc();
         return Ok(());
     }
-    let xp_count: i64 = rand::thread_rng().gen_range(15..=25);
-    // this query is pretty nice. it handles most of the update logic for us. Pretty slow, though- ~100ms total.
+    let roles = msg.member.as_ref().map_or(&[][..], |m| m.roles.as_slice());
+    let boosts = crate::multipliers::find_boosts(&config, roles, msg.channel_id, &state).await?;
+    // gen_range panics on an inverted range, which the database shouldn't let happen anyway
+    let (low, high) = if config.min_xp <= config.max_xp {
+        (config.min_xp, config.max_xp)
+    } else {
+        (config.max_xp, config.min_xp)
+    };
+    let roll: i64 = rand::thread_rng().gen_range(low..=high);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    let xp_count = (roll as f64 * boosts.total()).round() as i64;
+    // this query is pretty nice. it handles most of the update logic for us, and logs the event
+    // in the same round trip. Pretty slow, though- ~100ms total.
+    let timer = state.metrics.query_timer("message_xp");
     #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
     let xp = query!(
-        "INSERT INTO levels (id, xp, guild) VALUES ($1, $2, $3) ON CONFLICT (id, guild)
-         DO UPDATE SET xp=levels.xp+excluded.xp RETURNING xp",
+        r#"WITH updated AS (
+            INSERT INTO levels (id, xp, guild) VALUES ($1, $2, $3) ON CONFLICT (id, guild)
+            DO UPDATE SET xp=levels.xp+excluded.xp RETURNING xp
+         ), event AS (
+            INSERT INTO xp_events (guild, id, delta, source) VALUES ($3, $1, $2, $4)
+         )
+         SELECT xp AS "xp!" FROM updated"#,
         msg.author.id.get() as i64,
         xp_count,
-        guild_id.get() as i64
+        guild_id.get() as i64,
+        XpSource::Message.as_str()
     )
     .fetch_one(&state.db)
     .await?
     .xp as u64;
-    // once you're in the DB with no errors, cooldown it.
-    state.cooldowns.add(guild_id, msg.author.id).await;
+    timer.observe_duration();
+    #[allow(clippy::cast_sign_loss)]
+    state.metrics.xp_awarded.inc_by(xp_count.max(0) as u64);
     let level_info = mee6::LevelInfo::new(xp);
+    #[allow(clippy::cast_sign_loss)]
+    let old_level = mee6::LevelInfo::new(xp - xp_count as u64).level();
     #[allow(clippy::cast_sign_loss, clippy::cast_possible_wrap)]
     let reward = query!(
         "SELECT id FROM role_rewards
@@ -40,15 +90,117 @@ pub async fn save(msg: MessageCreate, state: AppState) -> Result<(), crate::Erro
     .await?
     .map(|v| Id::<RoleMarker>::new(v.id as u64));
     if let Some(reward) = reward {
-        if let Some(member) = &msg.member {
-            if member.roles.contains(&reward) {
-                return Ok(());
-            }
+        let has_reward = msg
+            .member
+            .as_ref()
+            .is_some_and(|member| member.roles.contains(&reward));
+        if !has_reward {
+            state
+                .client
+                .add_guild_member_role(guild_id, msg.author.id, reward)
+                .await?;
+            state.metrics.rewards_granted.inc();
         }
-        state
-            .client
-            .add_guild_member_role(guild_id, msg.author.id, reward)
-            .await?;
     }
+    if level_info.level() > old_level && config.level_up_mode != LevelUpMode::Off {
+        announce_level_up(&config, guild_id, msg.channel_id, msg.author.id, xp, &state).await?;
+    }
+    Ok(())
+}
+
+// The placeholders look like format arguments to clippy, but they're filled in by hand.
+#[allow(clippy::literal_string_with_formatting_args)]
+async fn announce_level_up(
+    config: &GuildConfig,
+    guild_id: Id<GuildMarker>,
+    message_channel: Id<ChannelMarker>,
+    user_id: Id<UserMarker>,
+    xp: u64,
+    state: &AppState,
+) -> Result<(), crate::Error> {
+    let channel_id = match config.level_up_mode {
+        LevelUpMode::Off => return Ok(()),
+        LevelUpMode::Current => message_channel,
+        LevelUpMode::Channel => config.level_up_channel.unwrap_or(message_channel),
+        LevelUpMode::Dm => {
+            state
+                .client
+                .create_private_channel(user_id)
+                .await?
+                .model()
+                .await?
+                .id
+        }
+    };
+    let template = config
+        .level_up_message
+        .as_deref()
+        .unwrap_or(crate::config::DEFAULT_LEVEL_UP_MESSAGE);
+    // Rank costs a query, so only look it up for templates that show it.
+    #[allow(clippy::cast_possible_wrap)]
+    let rank = if template.contains("{rank}") {
+        query!(
+            "SELECT COUNT(*) as count FROM levels WHERE xp > $1 AND guild = $2",
+            xp as i64,
+            guild_id.get() as i64
+        )
+        .fetch_one(&state.db)
+        .await?
+        .count
+        .unwrap_or(0)
+            + 1
+    } else {
+        0
+    };
+    let content = fill_template(template, user_id, xp, rank);
+    // Only ever ping the user who leveled up, no matter what the template says.
+    let allowed_mentions = AllowedMentions {
+        users: vec![user_id],
+        ..AllowedMentions::default()
+    };
+    state
+        .client
+        .create_message(channel_id)
+        .content(&content)?
+        .allowed_mentions(Some(&allowed_mentions))
+        .await?;
     Ok(())
 }
+
+/// Fills in a level-up message's `{user}`, `{level}`, `{rank}` and `{xp}`.
+#[allow(clippy::literal_string_with_formatting_args)]
+fn fill_template(template: &str, user_id: Id<UserMarker>, xp: u64, rank: i64) -> String {
+    template
+        .replace("{user}", &format!("<@{user_id}>"))
+        .replace("{level}", &mee6::LevelInfo::new(xp).level().to_string())
+        .replace("{rank}", &rank.to_string())
+        .replace("{xp}", &xp.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_every_placeholder() {
+        let filled = fill_template(
+            "{user} is level {level}, rank {rank}, with {xp} XP",
+            Id::new(1234),
+            255,
+            3,
+        );
+        assert_eq!(filled, "<@1234> is level 2, rank 3, with 255 XP");
+    }
+
+    #[test]
+    fn fills_repeated_placeholders_and_leaves_others_alone() {
+        let filled = fill_template("{level} {level} {unknown}", Id::new(1), 0, 0);
+        assert_eq!(filled, "0 0 {unknown}");
+    }
+
+    #[test]
+    fn default_message() {
+        let filled = fill_template(crate::config::DEFAULT_LEVEL_UP_MESSAGE, Id::new(99), 100, 1);
+        assert_eq!(filled, "Congratulations <@99>, you reached level 1!");
+    }
+}
@@ -0,0 +1,259 @@
+use axum::{extract::State, http::StatusCode, routing::get, Router};
+use prometheus::{
+    Encoder, GaugeVec, Histogram, HistogramOpts, HistogramTimer, HistogramVec, IntCounter,
+    IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use twilight_gateway::{ConnectionStatus, Shard};
+use twilight_model::application::interaction::{Interaction, InteractionData, InteractionType};
+
+use crate::Error;
+
+const SHARD_STATUSES: [&str; 5] = [
+    "connected",
+    "disconnected",
+    "fatally_closed",
+    "identifying",
+    "resuming",
+];
+
+/// Everything we count for Prometheus. The metric types are all reference counted,
+/// so cloning this is cheap and every clone counts into the same place.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub messages: IntCounter,
+    pub cooldown_hits: IntCounter,
+    pub xp_awarded: IntCounter,
+    pub rewards_granted: IntCounter,
+    interactions: IntCounterVec,
+    interaction_errors: IntCounterVec,
+    pub card_render: Histogram,
+    db_queries: HistogramVec,
+    shard_status: IntGaugeVec,
+    shard_latency: GaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let metrics = Self {
+            registry: Registry::new_custom(Some("minixpd".to_string()), None)
+                .expect("Failed to create metrics registry"),
+            messages: IntCounter::new(
+                "messages_processed_total",
+                "Guild messages from users, whether or not they earned XP",
+            )
+            .expect("Failed to create messages metric"),
+            cooldown_hits: IntCounter::new(
+                "cooldown_hits_total",
+                "Messages that earned nothing because their author was on cooldown",
+            )
+            .expect("Failed to create cooldown metric"),
+            xp_awarded: IntCounter::new("xp_awarded_total", "XP given out for messages")
+                .expect("Failed to create XP metric"),
+            rewards_granted: IntCounter::new(
+                "role_rewards_granted_total",
+                "Reward roles given to members for reaching a level",
+            )
+            .expect("Failed to create rewards metric"),
+            interactions: IntCounterVec::new(
+                Opts::new("interactions_total", "Interactions handled"),
+                &["command"],
+            )
+            .expect("Failed to create interactions metric"),
+            interaction_errors: IntCounterVec::new(
+                Opts::new(
+                    "interaction_errors_total",
+                    "Interactions that were answered with an error",
+                ),
+                &["command", "error"],
+            )
+            .expect("Failed to create interaction errors metric"),
+            card_render: Histogram::with_opts(HistogramOpts::new(
+                "card_render_seconds",
+                "Time taken to draw a rank card, including fetching the avatar",
+            ))
+            .expect("Failed to create card render metric"),
+            db_queries: HistogramVec::new(
+                HistogramOpts::new(
+                    "db_query_seconds",
+                    "Time taken by the database queries that run most often",
+                )
+                .buckets(prometheus::exponential_buckets(0.0005, 2.0, 14).expect("bad buckets")),
+                &["query"],
+            )
+            .expect("Failed to create database query metric"),
+            shard_status: IntGaugeVec::new(
+                Opts::new(
+                    "gateway_shard_status",
+                    "1 for the status each gateway shard is in, 0 for the rest",
+                ),
+                &["shard", "status"],
+            )
+            .expect("Failed to create shard status metric"),
+            shard_latency: GaugeVec::new(
+                Opts::new(
+                    "gateway_shard_latency_seconds",
+                    "Average heartbeat round trip time of each gateway shard",
+                ),
+                &["shard"],
+            )
+            .expect("Failed to create shard latency metric"),
+        };
+        let collectors: [Box<dyn prometheus::core::Collector>; 10] = [
+            Box::new(metrics.messages.clone()),
+            Box::new(metrics.cooldown_hits.clone()),
+            Box::new(metrics.xp_awarded.clone()),
+            Box::new(metrics.rewards_granted.clone()),
+            Box::new(metrics.interactions.clone()),
+            Box::new(metrics.interaction_errors.clone()),
+            Box::new(metrics.card_render.clone()),
+            Box::new(metrics.db_queries.clone()),
+            Box::new(metrics.shard_status.clone()),
+            Box::new(metrics.shard_latency.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Failed to register metric");
+        }
+        metrics
+    }
+
+    /// Counts an interaction, and what went wrong with it if anything did.
+    pub fn interaction(&self, command: &str, error: Option<&Error>) {
+        self.interactions.with_label_values(&[command]).inc();
+        if let Some(error) = error {
+            self.interaction_errors
+                .with_label_values(&[command, error_name(error)])
+                .inc();
+        }
+    }
+
+    /// Starts timing a database query. The time is recorded when the timer is dropped,
+    /// or earlier with `observe_duration`.
+    pub fn query_timer(&self, query: &str) -> HistogramTimer {
+        self.db_queries.with_label_values(&[query]).start_timer()
+    }
+
+    /// Times a query that's a single future, however it finishes.
+    pub async fn timed<T>(&self, query: &str, future: impl std::future::Future<Output = T>) -> T {
+        let _timer = self.query_timer(query);
+        future.await
+    }
+
+    /// Records where a shard is at. This is called every time the shard gives us anything.
+    pub fn shard(&self, shard: &Shard) {
+        let id = shard.id().number().to_string();
+        let current = match shard.status() {
+            ConnectionStatus::Connected => "connected",
+            ConnectionStatus::Disconnected { .. } => "disconnected",
+            ConnectionStatus::FatallyClosed { .. } => "fatally_closed",
+            ConnectionStatus::Identifying => "identifying",
+            ConnectionStatus::Resuming => "resuming",
+        };
+        for status in SHARD_STATUSES {
+            self.shard_status
+                .with_label_values(&[&id, status])
+                .set(i64::from(status == current));
+        }
+        if let Some(latency) = shard.latency().average() {
+            self.shard_latency
+                .with_label_values(&[&id])
+                .set(latency.as_secs_f64());
+        }
+    }
+
+    fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+/// The name interactions are counted under. Buttons and modals don't say which command
+/// made them, so they're counted by kind.
+pub fn command_name(interaction: &Interaction) -> String {
+    match &interaction.data {
+        Some(InteractionData::ApplicationCommand(data))
+            if interaction.kind == InteractionType::ApplicationCommandAutocomplete =>
+        {
+            format!("{}:autocomplete", data.name)
+        }
+        Some(InteractionData::ApplicationCommand(data)) => data.name.clone(),
+        Some(InteractionData::MessageComponent(_)) => "component".to_string(),
+        Some(InteractionData::ModalSubmit(_)) => "modal".to_string(),
+        _ => "other".to_string(),
+    }
+}
+
+// Matching every variant means a new one can't be added without giving it a label.
+const fn error_name(error: &Error) -> &'static str {
+    match error {
+        Error::UnrecognizedCommand => "UnrecognizedCommand",
+        Error::NoInvoker => "NoInvoker",
+        Error::NoTarget => "NoTarget",
+        Error::NoResolvedData => "NoResolvedData",
+        Error::NoMessageTargetId => "NoMessageTargetId",
+        Error::WrongInteractionData => "WrongInteractionData",
+        Error::NoInteractionData => "NoInteractionData",
+        Error::NoGuildId => "NoGuildId",
+        Error::TooMuchXp => "TooMuchXp",
+        Error::NoUsersForPage => "NoUsersForPage",
+        Error::NoModalActionRow => "NoModalActionRow",
+        Error::NoFormField => "NoFormField",
+        Error::NoDestinationInComponent => "NoDestinationInComponent",
+        Error::WrongArgumentCount => "WrongArgumentCount",
+        Error::EveryoneReward => "EveryoneReward",
+        Error::ManagedRoleReward => "ManagedRoleReward",
+        Error::RoleTooHigh => "RoleTooHigh",
+        Error::BotsNotRanked => "BotsNotRanked",
+        Error::NotOnLeaderboard => "NotOnLeaderboard",
+        Error::UserNotOnLeaderboard(..) => "UserNotOnLeaderboard",
+        Error::NoSuchMember(..) => "NoSuchMember",
+        Error::InvertedXpRange => "InvertedXpRange",
+        Error::NoLevelUpChannel => "NoLevelUpChannel",
+        Error::NoChannelOrRole => "NoChannelOrRole",
+        Error::Mee6LeaderboardUnavailable => "Mee6LeaderboardUnavailable",
+        Error::InvalidMee6UserId(..) => "InvalidMee6UserId",
+        Error::ImportFileTooLarge => "ImportFileTooLarge",
+        Error::ImportNotJsonArray => "ImportNotJsonArray",
+        Error::ImportTooMuchXp(..) => "ImportTooMuchXp",
+        Error::InvalidColor(..) => "InvalidColor",
+        Error::LowContrast(..) => "LowContrast",
+        Error::CardThemeForced => "CardThemeForced",
+        Error::UnknownToy(..) => "UnknownToy",
+        Error::SeasonExists(..) => "SeasonExists",
+        Error::UnknownSeason(..) => "UnknownSeason",
+        Error::SeasonWithPeriod => "SeasonWithPeriod",
+        Error::InvalidCustomButtonId => "InvalidCustomButtonId",
+        Error::CustomIdParseFailure(..) => "CustomIdParseFailure",
+        Error::ValidateMessage(..) => "ValidateMessage",
+        Error::Parse(..) => "Parse",
+        Error::ImageSource(..) => "ImageSource",
+        Error::ImageGenerator(..) => "ImageGenerator",
+        Error::Json(..) => "Json",
+        Error::ChartSvg(..) => "ChartSvg",
+        Error::ChartPng(..) => "ChartPng",
+        Error::ChartPixmap => "ChartPixmap",
+        Error::ChartTask(..) => "ChartTask",
+        Error::Redis(..) => "Redis",
+        Error::Sqlx(..) => "Sqlx",
+        Error::TwilightHttp(..) => "TwilightHttp",
+        Error::DeserializeBody(..) => "DeserializeBody",
+        Error::ReqwestHttp(..) => "ReqwestHttp",
+    }
+}
+
+pub fn router(metrics: Metrics) -> Router {
+    Router::new()
+        .route("/metrics", get(serve))
+        .with_state(metrics)
+}
+
+async fn serve(State(metrics): State<Metrics>) -> Result<String, StatusCode> {
+    metrics.render().map_err(|e| {
+        warn!("Failed to encode metrics: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
@@ -0,0 +1,235 @@
+use std::fmt::{Display, Write};
+
+use twilight_model::{
+    http::interaction::InteractionResponse,
+    id::{
+        marker::{ChannelMarker, GuildMarker, RoleMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+
+use crate::{
+    cmd_defs::{MultipliersCommand, MultipliersRemove, MultipliersSet},
+    config::GuildConfig,
+    ephemeral_embed_response, AppState, Error,
+};
+
+/// The multipliers that apply to one message.
+///
+/// Only the single highest role multiplier counts, so stacking booster roles doesn't add up.
+/// For channels, the most specific multiplier wins: a thread's own beats its parent channel's,
+/// which beats the category's. The role and channel multipliers are then multiplied together,
+/// so a 1.5× role in a 2× channel earns 3×.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Boosts {
+    pub role: Option<(Id<RoleMarker>, f64)>,
+    pub channel: Option<(Id<ChannelMarker>, f64)>,
+}
+
+impl Boosts {
+    pub fn total(&self) -> f64 {
+        self.role.map_or(1.0, |v| v.1) * self.channel.map_or(1.0, |v| v.1)
+    }
+    pub const fn is_empty(&self) -> bool {
+        self.role.is_none() && self.channel.is_none()
+    }
+}
+
+impl Display for Boosts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("**Active boosts:**")?;
+        if let Some((role, multiplier)) = self.role {
+            write!(f, " {multiplier}× from <@&{role}>")?;
+        }
+        if let Some((channel, multiplier)) = self.channel {
+            write!(f, " {multiplier}× in <#{channel}>")?;
+        }
+        write!(f, " ({}× total)", self.total())
+    }
+}
+
+pub async fn find_boosts(
+    config: &GuildConfig,
+    roles: &[Id<RoleMarker>],
+    channel_id: Id<ChannelMarker>,
+    state: &AppState,
+) -> Result<Boosts, Error> {
+    let role = roles
+        .iter()
+        .filter_map(|role| Some((*role, *config.role_multipliers.get(role)?)))
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    // Looking up parents can cost a request, so don't unless there is something to find.
+    let channel = if config.channel_multipliers.is_empty() {
+        None
+    } else {
+        state
+            .channel_parents
+            .closest(channel_id, &state.client, |channel| {
+                Some((channel, *config.channel_multipliers.get(&channel)?))
+            })
+            .await?
+    };
+    Ok(Boosts { role, channel })
+}
+
+pub async fn process_multipliers(
+    cmd: MultipliersCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let description = match cmd {
+        MultipliersCommand::Set(set) => set_multiplier(set, guild_id, &state).await?,
+        MultipliersCommand::Remove(remove) => remove_multiplier(remove, guild_id, &state).await?,
+        MultipliersCommand::List(_) => {
+            let config = state.configs.get(guild_id, &state.db).await?;
+            list_multipliers(&config)
+        }
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+async fn set_multiplier(
+    options: MultipliersSet,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<String, Error> {
+    let mut config = (*state.configs.get(guild_id, &state.db).await?).clone();
+    #[allow(clippy::cast_possible_wrap)]
+    let description = match (options.channel, options.role) {
+        (Some(channel), None) => {
+            query!(
+                "INSERT INTO channel_multipliers (id, guild, multiplier) VALUES ($1, $2, $3)
+                 ON CONFLICT (guild, id) DO UPDATE SET multiplier = excluded.multiplier",
+                channel.get() as i64,
+                guild_id.get() as i64,
+                options.multiplier
+            )
+            .execute(&state.db)
+            .await?;
+            config
+                .channel_multipliers
+                .insert(channel, options.multiplier);
+            format!(
+                "Messages in <#{channel}> now earn {}× XP.",
+                options.multiplier
+            )
+        }
+        (None, Some(role)) => {
+            query!(
+                "INSERT INTO role_multipliers (id, guild, multiplier) VALUES ($1, $2, $3)
+                 ON CONFLICT (guild, id) DO UPDATE SET multiplier = excluded.multiplier",
+                role.get() as i64,
+                guild_id.get() as i64,
+                options.multiplier
+            )
+            .execute(&state.db)
+            .await?;
+            config.role_multipliers.insert(role, options.multiplier);
+            format!(
+                "Members with <@&{role}> now earn {}× XP.",
+                options.multiplier
+            )
+        }
+        _ => return Err(Error::NoChannelOrRole),
+    };
+    state.configs.set(guild_id, config);
+    Ok(description)
+}
+
+async fn remove_multiplier(
+    options: MultipliersRemove,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<String, Error> {
+    let mut config = (*state.configs.get(guild_id, &state.db).await?).clone();
+    #[allow(clippy::cast_possible_wrap)]
+    let description = match (options.channel, options.role) {
+        (Some(channel), None) => {
+            query!(
+                "DELETE FROM channel_multipliers WHERE id = $1 AND guild = $2",
+                channel.get() as i64,
+                guild_id.get() as i64
+            )
+            .execute(&state.db)
+            .await?;
+            config.channel_multipliers.remove(&channel);
+            format!("Messages in <#{channel}> earn normal XP again.")
+        }
+        (None, Some(role)) => {
+            query!(
+                "DELETE FROM role_multipliers WHERE id = $1 AND guild = $2",
+                role.get() as i64,
+                guild_id.get() as i64
+            )
+            .execute(&state.db)
+            .await?;
+            config.role_multipliers.remove(&role);
+            format!("Members with <@&{role}> earn normal XP again.")
+        }
+        _ => return Err(Error::NoChannelOrRole),
+    };
+    state.configs.set(guild_id, config);
+    Ok(description)
+}
+
+fn list_multipliers(config: &GuildConfig) -> String {
+    if config.channel_multipliers.is_empty() && config.role_multipliers.is_empty() {
+        return "There are no XP multipliers in this server.".to_string();
+    }
+    let mut description = String::with_capacity(
+        (config.channel_multipliers.len() + config.role_multipliers.len()) * 40,
+    );
+    if !config.channel_multipliers.is_empty() {
+        description += "**Channels:**\n";
+        for (channel, multiplier) in &config.channel_multipliers {
+            writeln!(description, "<#{channel}>: {multiplier}×").ok();
+        }
+    }
+    if !config.role_multipliers.is_empty() {
+        description += "**Roles:**\n";
+        for (role, multiplier) in &config.role_multipliers {
+            writeln!(description, "<@&{role}>: {multiplier}×").ok();
+        }
+    }
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_boosts_is_one() {
+        let boosts = Boosts::default();
+        assert!(boosts.is_empty());
+        assert!((boosts.total() - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn role_and_channel_multiply() {
+        let boosts = Boosts {
+            role: Some((Id::new(1), 1.5)),
+            channel: Some((Id::new(2), 2.0)),
+        };
+        assert!(!boosts.is_empty());
+        assert!((boosts.total() - 3.0).abs() < f64::EPSILON);
+        assert_eq!(
+            boosts.to_string(),
+            "**Active boosts:** 1.5× from <@&1> 2× in <#2> (3× total)"
+        );
+    }
+
+    #[test]
+    fn one_side_only() {
+        let boosts = Boosts {
+            role: None,
+            channel: Some((Id::new(2), 0.5)),
+        };
+        assert!((boosts.total() - 0.5).abs() < f64::EPSILON);
+    }
+}
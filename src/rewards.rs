@@ -0,0 +1,163 @@
+use std::fmt::Write;
+
+use twilight_model::{
+    http::interaction::InteractionResponse,
+    id::{
+        marker::{GuildMarker, RoleMarker},
+        Id,
+    },
+};
+use twilight_util::builder::embed::EmbedBuilder;
+
+use crate::{
+    cmd_defs::{RewardsAdd, RewardsCommand, RewardsRemove},
+    ephemeral_embed_response, AppState, Error,
+};
+
+pub async fn process_rewards(
+    cmd: RewardsCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let description = match cmd {
+        RewardsCommand::Add(add) => {
+            check_grantable(add.role, guild_id, &state.client).await?;
+            add_reward(add, guild_id, &state.db).await?
+        }
+        RewardsCommand::Remove(remove) => remove_reward(remove, guild_id, &state.db).await?,
+        RewardsCommand::List(_) => list_rewards(guild_id, &state.db).await?,
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+/// Discord won't let us hand out @everyone, roles owned by integrations,
+/// or roles that aren't below our own highest role.
+async fn check_grantable(
+    role_id: Id<RoleMarker>,
+    guild_id: Id<GuildMarker>,
+    client: &twilight_http::Client,
+) -> Result<(), Error> {
+    if role_id.cast() == guild_id {
+        return Err(Error::EveryoneReward);
+    }
+    let roles = client.roles(guild_id).await?.models().await?;
+    let role = roles
+        .iter()
+        .find(|role| role.id == role_id)
+        .ok_or(Error::NoResolvedData)?;
+    if role.managed {
+        return Err(Error::ManagedRoleReward);
+    }
+    let me = client.current_user().await?.model().await?.id;
+    let member = client.guild_member(guild_id, me).await?.model().await?;
+    let highest = roles
+        .iter()
+        .filter(|role| member.roles.contains(&role.id))
+        .map(|role| role.position)
+        .max()
+        .unwrap_or(0);
+    if role.position >= highest {
+        return Err(Error::RoleTooHigh);
+    }
+    Ok(())
+}
+
+async fn add_reward(
+    options: RewardsAdd,
+    guild_id: Id<GuildMarker>,
+    db: &sqlx::PgPool,
+) -> Result<String, Error> {
+    // Each role and each level can only have one reward, so adding a role that is
+    // already a reward moves it to the new level, and adding to a taken level replaces the role.
+    let mut txn = db.begin().await?;
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "DELETE FROM role_rewards WHERE guild = $1 AND id = $2",
+        guild_id.get() as i64,
+        options.role.get() as i64
+    )
+    .execute(&mut txn)
+    .await?;
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO role_rewards (id, requirement, guild) VALUES ($1, $2, $3)
+         ON CONFLICT (guild, requirement) DO UPDATE SET id = excluded.id",
+        options.role.get() as i64,
+        options.level,
+        guild_id.get() as i64
+    )
+    .execute(&mut txn)
+    .await?;
+    txn.commit().await?;
+    Ok(format!(
+        "Members will now get <@&{}> at level {}!",
+        options.role, options.level
+    ))
+}
+
+async fn remove_reward(
+    options: RewardsRemove,
+    guild_id: Id<GuildMarker>,
+    db: &sqlx::PgPool,
+) -> Result<String, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let removed = match (options.level, options.role) {
+        (Some(level), None) => query!(
+            "DELETE FROM role_rewards WHERE guild = $1 AND requirement = $2 RETURNING id",
+            guild_id.get() as i64,
+            level
+        )
+        .fetch_optional(db)
+        .await?
+        .map(|v| v.id),
+        (None, Some(role)) => query!(
+            "DELETE FROM role_rewards WHERE guild = $1 AND id = $2 RETURNING id",
+            guild_id.get() as i64,
+            role.get() as i64
+        )
+        .fetch_optional(db)
+        .await?
+        .map(|v| v.id),
+        _ => return Err(Error::WrongArgumentCount),
+    };
+    #[allow(clippy::cast_sign_loss)]
+    let description = removed.map_or_else(
+        || "There is no role reward matching that!".to_string(),
+        |id| {
+            format!(
+                "Removed role reward <@&{}>!",
+                Id::<RoleMarker>::new(id as u64)
+            )
+        },
+    );
+    Ok(description)
+}
+
+async fn list_rewards(guild_id: Id<GuildMarker>, db: &sqlx::PgPool) -> Result<String, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let rewards = query!(
+        "SELECT id, requirement FROM role_rewards WHERE guild = $1 ORDER BY requirement",
+        guild_id.get() as i64
+    )
+    .fetch_all(db)
+    .await?;
+    if rewards.is_empty() {
+        return Ok("There are no role rewards in this server!".to_string());
+    }
+    let mut description = String::with_capacity(rewards.len() * 48);
+    #[allow(clippy::cast_sign_loss)]
+    for reward in rewards {
+        writeln!(
+            description,
+            "**Level {}:** <@&{}>",
+            reward.requirement,
+            Id::<RoleMarker>::new(reward.id as u64)
+        )
+        .ok();
+    }
+    Ok(description)
+}
@@ -0,0 +1,347 @@
+use std::{fmt::Write, time::Duration};
+
+use twilight_model::{
+    application::interaction::message_component::MessageComponentInteractionData,
+    channel::message::{
+        component::{ActionRow, Button, ButtonStyle},
+        Component, MessageFlags,
+    },
+    http::interaction::{InteractionResponse, InteractionResponseType},
+    id::{marker::GuildMarker, Id},
+};
+use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
+
+use crate::{
+    cmd_defs::{SeasonCommand, SeasonEnd, SeasonSchedule},
+    ephemeral_embed_response,
+    events::XpSource,
+    AppState, Error,
+};
+
+// Discord only lets us show so much in one embed
+const MAX_LISTED_SEASONS: i64 = 25;
+
+/// Every button on the end-of-season confirmation starts with this, so dispatch can route it here.
+pub const END_ID_PREFIX: &str = "season_end_";
+const END_CANCEL_ID: &str = "season_end_cancel";
+const END_CONFIRM_PREFIX: &str = "season_end_confirm_";
+
+pub async fn process_seasons(
+    cmd: SeasonCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let description = match cmd {
+        SeasonCommand::End(end) => return end_prompt(&end, guild_id, &state).await,
+        SeasonCommand::Schedule(schedule) => set_schedule(schedule, guild_id, &state).await?,
+        SeasonCommand::Unschedule(_) => {
+            #[allow(clippy::cast_possible_wrap)]
+            let removed = query!(
+                "DELETE FROM season_schedules WHERE guild = $1",
+                guild_id.get() as i64
+            )
+            .execute(&state.db)
+            .await?
+            .rows_affected();
+            if removed == 0 {
+                "Seasons weren't scheduled to end automatically.".to_string()
+            } else {
+                "Seasons will no longer end automatically.".to_string()
+            }
+        }
+        SeasonCommand::List(_) => list_seasons(guild_id, &state.db).await?,
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+// Ending a season rewrites everyone's XP, so just like /xp reset, nothing happens until
+// the button is clicked. The carry over and name are stored in the button's custom ID.
+async fn end_prompt(
+    options: &SeasonEnd,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<InteractionResponse, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let taken = query!(
+        r#"SELECT EXISTS(SELECT 1 FROM seasons WHERE guild = $1 AND name = $2) AS "taken!""#,
+        guild_id.get() as i64,
+        options.name
+    )
+    .fetch_one(&state.db)
+    .await?
+    .taken;
+    if taken {
+        return Err(Error::SeasonExists(options.name.clone()));
+    }
+    let carry_over = options.carry_over.unwrap_or(0);
+    let kept = if carry_over == 0 {
+        "everyone will start the next one from zero".to_string()
+    } else {
+        format!("everyone will keep {carry_over}% of their XP")
+    };
+    let confirm_button = Component::Button(Button {
+        custom_id: Some(format!("{END_CONFIRM_PREFIX}{carry_over}:{}", options.name)),
+        disabled: false,
+        emoji: None,
+        label: Some("End season".to_string()),
+        style: ButtonStyle::Danger,
+        url: None,
+    });
+    let cancel_button = Component::Button(Button {
+        custom_id: Some(END_CANCEL_ID.to_string()),
+        disabled: false,
+        emoji: None,
+        label: Some("Cancel".to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    });
+    let embed = EmbedBuilder::new()
+        .description(format!(
+            "Are you sure you want to end **{}**? Its leaderboard will be saved, and {kept}.",
+            options.name
+        ))
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(InteractionResponse {
+        kind: InteractionResponseType::ChannelMessageWithSource,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .components([Component::ActionRow(ActionRow {
+                    components: vec![confirm_button, cancel_button],
+                })])
+                .embeds([embed])
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+        ),
+    })
+}
+
+pub async fn process_end_component(
+    data: MessageComponentInteractionData,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let description = if data.custom_id == END_CANCEL_ID {
+        "The season is still going, nothing was changed.".to_string()
+    } else {
+        let (carry_over, name) = data
+            .custom_id
+            .strip_prefix(END_CONFIRM_PREFIX)
+            .and_then(|rest| rest.split_once(':'))
+            .ok_or(Error::InvalidCustomButtonId)?;
+        end_now(name, carry_over.parse()?, guild_id, &state).await?
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    // Replace the prompt so the buttons can't be clicked twice
+    Ok(InteractionResponse {
+        kind: InteractionResponseType::UpdateMessage,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .components([])
+                .embeds([embed])
+                .build(),
+        ),
+    })
+}
+
+async fn end_now(
+    name: &str,
+    carry_over: i64,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<String, Error> {
+    let mut txn = state.db.begin().await?;
+    let archived = end_season(&mut txn, guild_id, name, carry_over).await?;
+    txn.commit().await?;
+    let kept = if carry_over == 0 {
+        "Everyone starts the next one from zero.".to_string()
+    } else {
+        format!("Everyone keeps {carry_over}% of their XP.")
+    };
+    Ok(format!(
+        "Ended **{name}** and saved the standings of {archived} members. {kept}"
+    ))
+}
+
+/// Copies the guild's levels into the archive under `name`, then shrinks everyone's XP
+/// to `carry_over` percent of what it was. Returns how many users were archived.
+async fn end_season(
+    txn: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    guild_id: Id<GuildMarker>,
+    name: &str,
+    carry_over: i64,
+) -> Result<u64, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let guild = guild_id.get() as i64;
+    let created = query!(
+        "INSERT INTO seasons (guild, name) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        guild,
+        name
+    )
+    .execute(&mut *txn)
+    .await?
+    .rows_affected();
+    if created == 0 {
+        return Err(Error::SeasonExists(name.to_string()));
+    }
+    // Hold on to everyone's XP until we're done, so nothing earned while this runs
+    // ends up in the new season without being archived, or the other way around.
+    query!("SELECT id FROM levels WHERE guild = $1 FOR UPDATE", guild)
+        .fetch_all(&mut *txn)
+        .await?;
+    let archived = query!(
+        "INSERT INTO season_levels (guild, season, id, xp) SELECT guild, $2, id, xp FROM levels WHERE guild = $1",
+        guild,
+        name
+    )
+    .execute(&mut *txn)
+    .await?
+    .rows_affected();
+    // The events have to be written before levels changes, because they're the difference from the old XP.
+    // They're logged as seasons, which the period leaderboards don't count as earned XP.
+    query!(
+        "INSERT INTO xp_events (guild, id, delta, source)
+         SELECT guild, id, xp * $2 / 100 - xp, $3 FROM levels WHERE guild = $1 AND xp * $2 / 100 <> xp",
+        guild,
+        carry_over,
+        XpSource::Season.as_str()
+    )
+    .execute(&mut *txn)
+    .await?;
+    query!(
+        "UPDATE levels SET xp = xp * $2 / 100 WHERE guild = $1",
+        guild,
+        carry_over
+    )
+    .execute(&mut *txn)
+    .await?;
+    // Nobody with zero XP should show up on the new leaderboard.
+    query!("DELETE FROM levels WHERE guild = $1 AND xp = 0", guild)
+        .execute(&mut *txn)
+        .await?;
+    Ok(archived)
+}
+
+async fn set_schedule(
+    options: SeasonSchedule,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+) -> Result<String, Error> {
+    let carry_over = options.carry_over.unwrap_or(0);
+    // Discord keeps both of these well inside an i32.
+    #[allow(clippy::cast_possible_wrap, clippy::cast_possible_truncation)]
+    let next_end = query!(
+        r#"INSERT INTO season_schedules (guild, every_days, carry_over, next_end)
+         VALUES ($1, $2, $3, now() + make_interval(days => $2))
+         ON CONFLICT (guild) DO UPDATE SET every_days = excluded.every_days,
+         carry_over = excluded.carry_over, next_end = excluded.next_end
+         RETURNING EXTRACT(EPOCH FROM next_end)::BIGINT AS "next_end!""#,
+        guild_id.get() as i64,
+        options.every_days as i32,
+        carry_over as i32
+    )
+    .fetch_one(&state.db)
+    .await?
+    .next_end;
+    Ok(format!(
+        "Seasons will now end every {} days, and everyone will keep {carry_over}% of their XP. \
+         The current season ends <t:{next_end}:R>, and will be named after the day it ends.",
+        options.every_days
+    ))
+}
+
+async fn list_seasons(guild_id: Id<GuildMarker>, db: &sqlx::PgPool) -> Result<String, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let guild = guild_id.get() as i64;
+    let seasons = query!(
+        r#"SELECT name, EXTRACT(EPOCH FROM ended_at)::BIGINT AS "ended_at!" FROM seasons
+         WHERE guild = $1 ORDER BY ended_at DESC LIMIT $2"#,
+        guild,
+        MAX_LISTED_SEASONS
+    )
+    .fetch_all(db)
+    .await?;
+    let schedule = query!(
+        r#"SELECT every_days, carry_over, EXTRACT(EPOCH FROM next_end)::BIGINT AS "next_end!"
+         FROM season_schedules WHERE guild = $1"#,
+        guild
+    )
+    .fetch_optional(db)
+    .await?;
+    let mut description = String::with_capacity(seasons.len() * 64 + 128);
+    if let Some(schedule) = schedule {
+        writeln!(
+            description,
+            "Seasons end every {} days, keeping {}% of XP. The current one ends <t:{}:R>.\n",
+            schedule.every_days, schedule.carry_over, schedule.next_end
+        )
+        .ok();
+    }
+    if seasons.is_empty() {
+        description += "No seasons have ended in this server yet.";
+    }
+    for season in seasons {
+        writeln!(
+            description,
+            "**{}** - ended <t:{}:d>",
+            season.name, season.ended_at
+        )
+        .ok();
+    }
+    Ok(description)
+}
+
+/// Ends every season whose schedule says it's over, checking every few minutes, forever.
+pub async fn rollover_task(db: sqlx::PgPool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(5 * 60));
+    loop {
+        interval.tick().await;
+        if let Err(e) = roll_over_due(&db).await {
+            warn!("Failed to end scheduled seasons: {e}");
+        }
+    }
+}
+
+async fn roll_over_due(db: &sqlx::PgPool) -> Result<(), Error> {
+    let due = query!(
+        r#"SELECT guild, carry_over, to_char(next_end AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS "name!"
+         FROM season_schedules WHERE next_end <= now()"#
+    )
+    .fetch_all(db)
+    .await?;
+    for season in due {
+        #[allow(clippy::cast_sign_loss)]
+        let guild_id = Id::<GuildMarker>::new(season.guild as u64);
+        let mut txn = db.begin().await?;
+        match end_season(&mut txn, guild_id, &season.name, season.carry_over.into()).await {
+            Ok(count) => info!(
+                "Ended season {} in {guild_id} for {count} users",
+                season.name
+            ),
+            // Someone already ended a season with this name by hand. Nothing was written, so
+            // the transaction is still good for moving the schedule along.
+            Err(Error::SeasonExists(name)) => {
+                warn!("Skipped ending season {name} in {guild_id}, it already exists");
+            }
+            Err(e) => return Err(e),
+        }
+        // Skip ahead by whole seasons, so a bot that was offline for a while doesn't end several at once.
+        query!(
+            "UPDATE season_schedules SET next_end = next_end + make_interval(
+                days => every_days * (FLOOR(EXTRACT(EPOCH FROM now() - next_end) / (every_days * 86400))::INT + 1)
+             ) WHERE guild = $1",
+            season.guild
+        )
+        .execute(&mut txn)
+        .await?;
+        txn.commit().await?;
+    }
+    Ok(())
+}
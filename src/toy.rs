@@ -1,25 +1,91 @@
-use std::fmt::Display;
-
+use ahash::AHashSet;
 use twilight_interactions::command::{CommandOption, CreateOption};
 use twilight_model::{
-    channel::message::{Embed, MessageFlags},
+    application::{
+        command::{CommandOptionChoice, CommandOptionChoiceValue},
+        interaction::application_command::{CommandData, CommandDataOption, CommandOptionValue},
+    },
     http::interaction::{InteractionResponse, InteractionResponseType},
     id::{
-        marker::{GuildMarker, UserMarker},
+        marker::{GuildMarker, RoleMarker, UserMarker},
         Id,
     },
     user::User,
 };
 use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
 
-use crate::{AppState, Error};
+use crate::{ephemeral_embed_response, AppState, Error};
+
+/// What to type into `/toy` to take your toy off.
+pub const NONE: &str = "None";
+// Discord won't show more autocomplete choices than this
+const MAX_CHOICES: i64 = 25;
+
+/// Where a toy pick applies. Picks for a server win over the one for everywhere.
+#[derive(Clone, Copy, Debug, Default, CreateOption, CommandOption)]
+pub enum ToyScope {
+    #[default]
+    #[option(name = "This server", value = "server")]
+    Server,
+    #[option(name = "Every server", value = "global")]
+    Global,
+}
+
+/// One entry in the `toys` table, minus the image, which only the card renderer needs.
+pub struct CatalogToy {
+    pub name: String,
+    pub level_requirement: Option<i64>,
+    pub allowed_users: Vec<i64>,
+    pub allowed_roles: Vec<i64>,
+}
+
+impl CatalogToy {
+    /// Looks a toy up by name, ignoring case, so typed names work as well as picked ones.
+    pub async fn find(name: &str, db: &sqlx::PgPool) -> Result<Option<Self>, Error> {
+        Ok(query_as!(
+            Self,
+            "SELECT name, level_requirement, allowed_users, allowed_roles
+             FROM toys WHERE lower(name) = lower($1)",
+            name.trim()
+        )
+        .fetch_optional(db)
+        .await?)
+    }
+    /// Toys without an allow-list are for everyone. Otherwise, you need to be on the
+    /// user list or have one of the roles.
+    #[allow(clippy::cast_possible_wrap)]
+    fn allows(&self, user_id: Id<UserMarker>, roles: &[Id<RoleMarker>]) -> bool {
+        (self.allowed_users.is_empty() && self.allowed_roles.is_empty())
+            || self.allowed_users.contains(&(user_id.get() as i64))
+            || roles
+                .iter()
+                .any(|role| self.allowed_roles.contains(&(role.get() as i64)))
+    }
+    /// Whether someone at `level` with `roles` can show this toy.
+    #[allow(clippy::cast_sign_loss)]
+    fn usable(&self, level: u64, user_id: Id<UserMarker>, roles: &[Id<RoleMarker>]) -> bool {
+        self.level_requirement
+            .is_none_or(|requirement| level >= requirement as u64)
+            && self.allows(user_id, roles)
+    }
+}
 
 pub async fn modify(
-    toy: Toy,
+    toy: String,
+    scope: ToyScope,
     guild_id: Id<GuildMarker>,
     invoker: User,
+    roles: Vec<Id<RoleMarker>>,
     state: AppState,
 ) -> Result<InteractionResponse, Error> {
+    if toy.trim().eq_ignore_ascii_case(NONE) {
+        let description = clear(scope, guild_id, &invoker, &state.db).await?;
+        let embed = EmbedBuilder::new().description(description).build();
+        return Ok(ephemeral_embed_response(embed));
+    }
+    let toy = CatalogToy::find(&toy, &state.db)
+        .await?
+        .ok_or(Error::UnknownToy(toy))?;
     #[allow(clippy::cast_possible_wrap)]
     let xp = query!(
         "SELECT * FROM levels WHERE id = $1 AND guild = $2",
@@ -31,136 +97,237 @@ pub async fn modify(
     .map_or(0, |r| r.xp);
     #[allow(clippy::cast_sign_loss)]
     let level_info = mee6::LevelInfo::new(xp as u64);
-    if let Some(level_requirement) = toy.level_requirement() {
-        if level_info.level() < level_requirement {
+    #[allow(clippy::cast_sign_loss)]
+    if let Some(level_requirement) = toy.level_requirement {
+        if level_info.level() < level_requirement as u64 {
             // i break the rules on error handling here. It does make nicer UX.
             let embed = EmbedBuilder::new()
                 .description(format!(
-                    "You need at least {} levels for {toy} (you have {})",
+                    "You need at least {} levels for {} (you have {})",
                     level_requirement,
+                    toy.name,
                     level_info.level()
                 ))
                 .build();
             return Ok(ephemeral_embed_response(embed));
         }
     }
-    #[allow(clippy::cast_sign_loss)]
-    if let Some(id_list) = toy.id_requirement() {
-        if !id_list.contains(&invoker.id) {
-            // i break the rules on error handling here. It does make nicer UX.
-            let embed = EmbedBuilder::new()
-                .description(
-                    "You need to be on the allow-list of the bot to use this icon!".to_string(),
-                )
-                .build();
-            return Ok(ephemeral_embed_response(embed));
-        }
+    let config = state.configs.get(guild_id, &state.db).await?;
+    // A toy for everywhere is just hidden in servers that don't allow it.
+    if matches!(scope, ToyScope::Server)
+        && !config.allowed_toys.is_empty()
+        && !config.allowed_toys.contains(&toy.name)
+    {
+        // i break the rules on error handling here. It does make nicer UX.
+        let embed = EmbedBuilder::new()
+            .description(format!(
+                "{} isn't one of the toys allowed in this server!",
+                toy.name
+            ))
+            .build();
+        return Ok(ephemeral_embed_response(embed));
+    }
+    if !toy.allows(invoker.id, &roles) {
+        // i break the rules on error handling here. It does make nicer UX.
+        let embed = EmbedBuilder::new()
+            .description(
+                "You need to be on the allow-list of the bot to use this icon!".to_string(),
+            )
+            .build();
+        return Ok(ephemeral_embed_response(embed));
     }
     #[allow(clippy::cast_possible_wrap)]
-    query!(
-        "INSERT INTO card_toy (id, guild_id, toy) VALUES ($1, $2, $3) ON CONFLICT (id, guild_id) DO UPDATE SET toy = excluded.toy",
-        invoker.id.get() as i64,
-        guild_id.get() as i64,
-        toy.value()
-    )
-    .execute(&state.db)
-    .await?;
-    let embed = EmbedBuilder::new()
-        .description(format!("Set your toy to {toy}!"))
-        .build();
+    let description = match scope {
+        ToyScope::Server => {
+            query!(
+                "INSERT INTO card_toy (id, guild_id, toy) VALUES ($1, $2, $3) ON CONFLICT (id, guild_id) DO UPDATE SET toy = excluded.toy",
+                invoker.id.get() as i64,
+                guild_id.get() as i64,
+                toy.name
+            )
+            .execute(&state.db)
+            .await?;
+            format!("Set your toy in this server to {}!", toy.name)
+        }
+        ToyScope::Global => {
+            query!(
+                "INSERT INTO global_toys (id, toy) VALUES ($1, $2) ON CONFLICT (id) DO UPDATE SET toy = excluded.toy",
+                invoker.id.get() as i64,
+                toy.name
+            )
+            .execute(&state.db)
+            .await?;
+            format!(
+                "Set your toy to {} everywhere you haven't picked one just for that server!",
+                toy.name
+            )
+        }
+    };
+    let embed = EmbedBuilder::new().description(description).build();
     Ok(ephemeral_embed_response(embed))
 }
 
-#[derive(Clone, Copy, Debug, CreateOption, CommandOption)]
-pub enum Toy {
-    #[option(name = "None", value = "None")]
-    None,
-    #[option(name = "Airplane", value = "airplane.png")]
-    Airplane,
-    #[option(name = "Bee", value = "bee.png")]
-    Bee,
-    #[option(name = "Biscuit", value = "biscuit.png")]
-    Biscuit,
-    #[option(name = "Chicken", value = "chicken.png")]
-    Chicken,
-    #[option(name = "Cow", value = "cow.png")]
-    Cow,
-    #[option(name = "Fox", value = "fox.png")]
-    Fox,
-    #[option(name = "Grass Block", value = "grassblock.png")]
-    GrassBlock,
-    #[option(name = "Parrot", value = "parrot.png")]
-    Parrot,
-    #[option(name = "Pickaxe", value = "pickaxe.png")]
-    Pickaxe,
-    #[option(name = "Pig", value = "pig.png")]
-    Pig,
-    #[option(name = "Blue Potion", value = "potion_blue.png")]
-    PotionBlue,
-    #[option(name = "Purple Potion", value = "potion_purple.png")]
-    PotionPurple,
-    #[option(name = "Red Potion", value = "potion_red.png")]
-    PotionRed,
-    #[option(name = "Sheep", value = "sheep.png")]
-    Sheep,
-    #[option(name = "Steve Hug", value = "steveheart.png")]
-    SteveHeart,
-    #[option(name = "Tree", value = "tree.png")]
-    Tree,
-}
-
-impl Toy {
-    pub const fn level_requirement(self) -> Option<u64> {
-        match self {
-            Self::Pickaxe => Some(10),
-            Self::Fox | Self::Parrot => Some(5),
-            _ => None,
+async fn clear(
+    scope: ToyScope,
+    guild_id: Id<GuildMarker>,
+    invoker: &User,
+    db: &sqlx::PgPool,
+) -> Result<&'static str, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let description = match scope {
+        ToyScope::Server => {
+            query!(
+                "DELETE FROM card_toy WHERE id = $1 AND guild_id = $2",
+                invoker.id.get() as i64,
+                guild_id.get() as i64
+            )
+            .execute(db)
+            .await?;
+            "Removed your toy for this server! Your toy for every server will show here instead, if you have one."
         }
-    }
-    pub fn id_requirement(self) -> Option<Vec<Id<UserMarker>>> {
-        match self {
-            Self::Airplane => Some(vec![
-                Id::new(788_222_689_126_776_832),
-                Id::new(526_092_507_965_161_474),
-            ]),
-            _ => None,
+        ToyScope::Global => {
+            query!(
+                "DELETE FROM global_toys WHERE id = $1",
+                invoker.id.get() as i64
+            )
+            .execute(db)
+            .await?;
+            "Removed your toy for every server! Toys you picked for a single server are still there."
         }
-    }
+    };
+    Ok(description)
 }
 
-impl Display for Toy {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let text = match self {
-            Self::None => "None",
-            Self::Airplane => "Airplane",
-            Self::Bee => "Bee",
-            Self::Biscuit => "Biscuit",
-            Self::Chicken => "Chicken",
-            Self::Cow => "Cow",
-            Self::Fox => "Fox",
-            Self::GrassBlock => "Grass Block",
-            Self::Parrot => "Parrot",
-            Self::Pickaxe => "Pickaxe",
-            Self::Pig => "Pig",
-            Self::PotionBlue => "Blue Potion",
-            Self::PotionPurple => "Purple Potion",
-            Self::PotionRed => "Red Potion",
-            Self::Sheep => "Sheep",
-            Self::SteveHeart => "Steve Hug",
-            Self::Tree => "Tree",
-        };
-        f.write_str(text)
-    }
+/// Finds the image for someone's toy in a guild. Their pick for the guild comes first, then
+/// their pick for everywhere, skipping any the guild doesn't allow or they can't use here.
+/// Picks for everywhere were only checked in the server they were made in, and levels and
+/// roles change, so every pick is checked again against this guild.
+pub async fn resolve(
+    user_id: Id<UserMarker>,
+    guild_id: Id<GuildMarker>,
+    level: u64,
+    allowed_toys: &AHashSet<String>,
+    db: &sqlx::PgPool,
+) -> Result<Option<xpd_rank_card::Toy>, Error> {
+    #[allow(clippy::cast_possible_wrap)]
+    let picks = query!(
+        r#"SELECT toys.name, toys.asset, toys.level_requirement, toys.allowed_users, toys.allowed_roles
+         FROM (
+            SELECT toy, 0 AS priority FROM card_toy WHERE id = $1 AND guild_id = $2
+            UNION ALL
+            SELECT toy, 1 AS priority FROM global_toys WHERE id = $1
+         ) AS picks JOIN toys ON toys.name = picks.toy ORDER BY picks.priority"#,
+        user_id.get() as i64,
+        guild_id.get() as i64
+    )
+    .fetch_all(db)
+    .await?;
+    // Most toys aren't locked to roles, so only look the member's roles up if we need them.
+    let roles = if picks.iter().any(|pick| !pick.allowed_roles.is_empty()) {
+        #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+        query!(
+            "SELECT role FROM member_roles WHERE guild = $1 AND id = $2",
+            guild_id.get() as i64,
+            user_id.get() as i64
+        )
+        .fetch_all(db)
+        .await?
+        .into_iter()
+        .map(|v| Id::new(v.role as u64))
+        .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(picks
+        .into_iter()
+        .find_map(|pick| {
+            let toy = CatalogToy {
+                name: pick.name,
+                level_requirement: pick.level_requirement,
+                allowed_users: pick.allowed_users,
+                allowed_roles: pick.allowed_roles,
+            };
+            (toy.usable(level, user_id, &roles)
+                && (allowed_toys.is_empty() || allowed_toys.contains(&toy.name)))
+            .then_some(pick.asset)
+        })
+        .and_then(|asset| xpd_rank_card::Toy::from_filename(&asset)))
 }
 
-fn ephemeral_embed_response(embed: Embed) -> InteractionResponse {
-    InteractionResponse {
-        kind: InteractionResponseType::ChannelMessageWithSource,
+/// Suggests toys from the catalog for whichever toy option is being typed in.
+pub async fn autocomplete(
+    data: CommandData,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let typed = focused(&data.options).unwrap_or_default();
+    let mut names: Vec<String> = query!(
+        "SELECT name FROM toys WHERE strpos(lower(name), lower($1)) > 0 ORDER BY name LIMIT $2",
+        typed,
+        MAX_CHOICES
+    )
+    .fetch_all(&state.db)
+    .await?
+    .into_iter()
+    .map(|v| v.name)
+    .collect();
+    // Only /toy itself can take a toy off.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    if data.name == "toy" && NONE.to_lowercase().contains(&typed.to_lowercase()) {
+        names.insert(0, NONE.to_string());
+        names.truncate(MAX_CHOICES as usize);
+    }
+    let choices = names.into_iter().map(|name| CommandOptionChoice {
+        name: name.clone(),
+        name_localizations: None,
+        value: CommandOptionChoiceValue::String(name),
+    });
+    Ok(InteractionResponse {
+        kind: InteractionResponseType::ApplicationCommandAutocompleteResult,
         data: Some(
             InteractionResponseDataBuilder::new()
-                .embeds([embed])
-                .flags(MessageFlags::EPHEMERAL)
+                .choices(choices)
                 .build(),
         ),
+    })
+}
+
+/// Finds what's been typed so far into the option discord wants suggestions for,
+/// looking inside subcommands too.
+fn focused(options: &[CommandDataOption]) -> Option<String> {
+    options.iter().find_map(|option| match &option.value {
+        CommandOptionValue::Focused(typed, _) => Some(typed.clone()),
+        CommandOptionValue::SubCommand(inner) | CommandOptionValue::SubCommandGroup(inner) => {
+            focused(inner)
+        }
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gated_toys_need_the_level_and_a_role() {
+        let toy = CatalogToy {
+            name: "Golden Pickaxe".to_string(),
+            level_requirement: Some(20),
+            allowed_users: vec![1],
+            allowed_roles: vec![10],
+        };
+        let user = Id::new(2);
+        let role = [Id::new(10)];
+        assert!(toy.usable(20, user, &role));
+        assert!(!toy.usable(19, user, &role));
+        assert!(!toy.usable(20, user, &[Id::new(11)]));
+        // being on the user list is as good as having the role
+        assert!(toy.usable(20, Id::new(1), &[]));
+        let open = CatalogToy {
+            level_requirement: None,
+            allowed_users: Vec::new(),
+            allowed_roles: Vec::new(),
+            ..toy
+        };
+        assert!(open.usable(0, user, &[]));
     }
 }
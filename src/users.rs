@@ -0,0 +1,95 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use parking_lot::RwLock;
+use twilight_model::{
+    id::{marker::UserMarker, Id},
+    user::User,
+};
+
+// How long we trust a user we looked up before asking discord again
+const USER_TTL: Duration = Duration::from_secs(60 * 60);
+// Past this many users, whoever we looked up longest ago is forgotten to make room
+const MAX_USERS: usize = 10_000;
+
+// Each user, and when we looked them up
+type UserMap = AHashMap<Id<UserMarker>, (User, Instant)>;
+
+/// Remembers the users that leaderboards show, so drawing or serving a page doesn't
+/// ask Discord about the same ten people every time.
+#[derive(Debug, Clone, Default)]
+pub struct UserCache {
+    users: Arc<RwLock<UserMap>>,
+    prefetching: Arc<AtomicBool>,
+}
+
+impl UserCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the user, from the cache if we've seen them recently. Users discord won't
+    /// tell us about come back as `None`, and aren't remembered.
+    pub async fn get(
+        &self,
+        user_id: Id<UserMarker>,
+        client: &twilight_http::Client,
+    ) -> Option<User> {
+        let cached = self.users.read().get(&user_id).cloned();
+        if let Some((user, fetched)) = cached {
+            if fetched.elapsed() < USER_TTL {
+                return Some(user);
+            }
+        }
+        let user = match client.user(user_id).await {
+            Ok(response) => response.model().await.ok()?,
+            Err(e) => {
+                warn!("Failed to look up {user_id}: {e}");
+                return None;
+            }
+        };
+        {
+            let mut users = self.users.write();
+            // Nobody else clears out old entries, so it happens here.
+            users.retain(|_, (_, fetched)| fetched.elapsed() < USER_TTL);
+            if users.len() >= MAX_USERS {
+                let oldest = users
+                    .iter()
+                    .min_by_key(|(_, (_, fetched))| *fetched)
+                    .map(|(id, _)| *id);
+                if let Some(oldest) = oldest {
+                    users.remove(&oldest);
+                }
+            }
+            users.insert(user_id, (user.clone(), Instant::now()));
+        }
+        Some(user)
+    }
+    /// Returns the user if we've looked them up before, however long ago, without asking discord.
+    pub fn cached(&self, user_id: Id<UserMarker>) -> Option<User> {
+        self.users
+            .read()
+            .get(&user_id)
+            .map(|(user, _)| user.clone())
+    }
+    /// Looks users up in the background, so they're cached next time. Only one of these runs
+    /// at a time, and any asked for while it's running are skipped, so however often
+    /// this is called, we only ever ask discord about one user at a time.
+    pub fn prefetch(&self, user_ids: Vec<Id<UserMarker>>, client: Arc<twilight_http::Client>) {
+        if user_ids.is_empty() || self.prefetching.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let cache = self.clone();
+        tokio::spawn(async move {
+            for user_id in user_ids {
+                cache.get(user_id, &client).await;
+            }
+            cache.prefetching.store(false, Ordering::Release);
+        });
+    }
+}
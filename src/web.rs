@@ -0,0 +1,198 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Html,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{GuildMarker, UserMarker},
+    Id,
+};
+
+use crate::{history::escape, AppState, Error};
+
+/// Read-only leaderboards for guilds that have made them public with `/config public`.
+/// Guilds that haven't look exactly like guilds we've never heard of.
+pub fn router(app: AppState) -> Router {
+    Router::new()
+        .route("/api/guilds/:guild/leaderboard", get(leaderboard_json))
+        .route("/api/guilds/:guild/users/:user", get(user_json))
+        .route("/guilds/:guild", get(leaderboard_html))
+        .with_state(app)
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    page: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct Leaderboard {
+    page: i64,
+    users: Vec<RankedUser>,
+}
+
+#[derive(Serialize)]
+struct RankedUser {
+    /// Sent as a string, since snowflakes don't fit in a javascript number.
+    id: Id<UserMarker>,
+    rank: i64,
+    xp: i64,
+    level: u64,
+    /// How far through their current level they are, from 0 to 1.
+    progress: f64,
+}
+
+impl RankedUser {
+    #[allow(clippy::cast_sign_loss)]
+    fn new(id: Id<UserMarker>, rank: i64, xp: i64) -> Self {
+        let info = mee6::LevelInfo::new(xp as u64);
+        Self {
+            id,
+            rank,
+            xp,
+            level: info.level(),
+            progress: info.percentage(),
+        }
+    }
+}
+
+async fn leaderboard_json(
+    State(state): State<AppState>,
+    Path(guild_id): Path<Id<GuildMarker>>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Leaderboard>, StatusCode> {
+    let page = query.page.unwrap_or(1);
+    Ok(Json(Leaderboard {
+        page,
+        users: public_page(guild_id, page, &state).await?,
+    }))
+}
+
+async fn user_json(
+    State(state): State<AppState>,
+    Path((guild_id, user_id)): Path<(Id<GuildMarker>, Id<UserMarker>)>,
+) -> Result<Json<RankedUser>, StatusCode> {
+    check_public(guild_id, &state).await?;
+    let (xp, rank) = crate::levels::xp_and_rank(guild_id, user_id, &state)
+        .await
+        .map_err(internal)?;
+    // Nobody with zero XP is on the leaderboard, so they aren't ranked either.
+    if xp == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(RankedUser::new(user_id, rank, xp)))
+}
+
+async fn leaderboard_html(
+    State(state): State<AppState>,
+    Path(guild_id): Path<Id<GuildMarker>>,
+    Query(query): Query<PageQuery>,
+) -> Result<Html<String>, StatusCode> {
+    let page = query.page.unwrap_or(1);
+    let users = public_page(guild_id, page, &state).await?;
+    let mut html = String::with_capacity(users.len() * 256 + 1024);
+    html += r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Leaderboard</title>
+<style>
+body { background: #1e1f22; color: #ffffff; font-family: sans-serif; max-width: 40rem; margin: 2rem auto; padding: 0 1rem; }
+li { background: #2b2d31; border-radius: 0.5rem; margin: 0.5rem 0; padding: 0.75rem 1rem; list-style: none; }
+.level { float: right; }
+progress { width: 100%; accent-color: #7289da; }
+a { color: #7289da; }
+</style>
+</head>
+<body>
+<h1>Leaderboard</h1>
+"#;
+    if users.is_empty() {
+        html += "<p>Nobody is on this page.</p>\n";
+    }
+    html += "<ol>\n";
+    // Anyone can load these pages, so they only use names we already know. Anyone we don't
+    // is looked up in the background, and shows up by ID until then.
+    let unknown = users
+        .iter()
+        .map(|user| user.id)
+        .filter(|id| state.users.cached(*id).is_none())
+        .collect();
+    state.users.prefetch(unknown, state.client.clone());
+    for user in &users {
+        let name = state
+            .users
+            .cached(user.id)
+            .map_or_else(|| user.id.to_string(), |user| user.name);
+        writeln!(
+            html,
+            r#"<li>#{} {} <span class="level">Level {} ({} XP)</span><progress value="{:.3}"></progress></li>"#,
+            user.rank,
+            escape(&name),
+            user.level,
+            user.xp,
+            user.progress
+        )
+        .ok();
+    }
+    html += "</ol>\n<p>";
+    if page > 1 {
+        write!(html, r#"<a href="?page={}">Previous</a> "#, page - 1).ok();
+    }
+    // A short page has to be the last one.
+    if users.len() == 10 {
+        write!(html, r#"<a href="?page={}">Next</a>"#, page + 1).ok();
+    }
+    html += "</p>\n</body>\n</html>\n";
+    Ok(Html(html))
+}
+
+async fn public_page(
+    guild_id: Id<GuildMarker>,
+    page: i64,
+    state: &AppState,
+) -> Result<Vec<RankedUser>, StatusCode> {
+    check_public(guild_id, state).await?;
+    // "zpage" means "zero-indexed page", like in the leaderboard command.
+    let zpage = crate::leaderboard::zero_indexed(page).ok_or(StatusCode::BAD_REQUEST)?;
+    let users = crate::leaderboard::all_time_page(guild_id, state, zpage)
+        .await
+        .map_err(internal)?;
+    #[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+    Ok(users
+        .into_iter()
+        .enumerate()
+        .map(|(i, (id, xp))| RankedUser::new(Id::new(id as u64), i as i64 + zpage * 10 + 1, xp))
+        .collect())
+}
+
+// Anyone can ask about any guild ID here, so this is one query that never touches the config
+// cache. Otherwise made-up IDs would each load a whole config and keep it forever.
+async fn check_public(guild_id: Id<GuildMarker>, state: &AppState) -> Result<(), StatusCode> {
+    #[allow(clippy::cast_possible_wrap)]
+    let public = query!(
+        "SELECT public_leaderboard FROM guild_config WHERE id = $1",
+        guild_id.get() as i64
+    )
+    .fetch_optional(&state.db)
+    .await
+    .map_err(|e| internal(e.into()))?
+    .is_some_and(|v| v.public_leaderboard);
+    if public {
+        Ok(())
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn internal(e: Error) -> StatusCode {
+    warn!("Web request failed: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
@@ -0,0 +1,223 @@
+use twilight_model::{
+    application::interaction::message_component::MessageComponentInteractionData,
+    channel::message::{
+        component::{ActionRow, Button, ButtonStyle},
+        Component, MessageFlags,
+    },
+    http::interaction::{InteractionResponse, InteractionResponseType},
+    id::{
+        marker::{GuildMarker, UserMarker},
+        Id,
+    },
+};
+use twilight_util::builder::{embed::EmbedBuilder, InteractionResponseDataBuilder};
+
+use crate::{
+    cmd_defs::{XpCommand, XpReset},
+    ephemeral_embed_response,
+    events::XpSource,
+    AppState, Error,
+};
+
+/// Every button on the reset confirmation prompt starts with this, so dispatch can route it here.
+pub const RESET_ID_PREFIX: &str = "xp_reset_";
+const RESET_CANCEL_ID: &str = "xp_reset_cancel";
+const RESET_CONFIRM_PREFIX: &str = "xp_reset_confirm_";
+const RESET_EVERYONE: &str = "all";
+
+pub async fn process_xp(
+    cmd: XpCommand,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let (user, xp) = match cmd {
+        XpCommand::Give(give) => {
+            if give.user.bot {
+                return Err(Error::BotsNotRanked);
+            }
+            let xp = change_xp(give.user.id, guild_id, &state, |xp| {
+                xp.checked_add(give.amount)
+            })
+            .await?;
+            (give.user, xp)
+        }
+        XpCommand::Take(take) => {
+            if take.user.bot {
+                return Err(Error::BotsNotRanked);
+            }
+            let xp = change_xp(take.user.id, guild_id, &state, |xp| {
+                xp.checked_sub(take.amount)
+            })
+            .await?;
+            (take.user, xp)
+        }
+        XpCommand::Set(set) => {
+            if set.user.bot {
+                return Err(Error::BotsNotRanked);
+            }
+            let xp = change_xp(set.user.id, guild_id, &state, |_| Some(set.amount)).await?;
+            (set.user, xp)
+        }
+        XpCommand::Reset(reset) => return Ok(reset_prompt(&reset)),
+    };
+    #[allow(clippy::cast_sign_loss)]
+    let level = mee6::LevelInfo::new(xp as u64).level();
+    let embed = EmbedBuilder::new()
+        .description(format!(
+            "<@{}> now has {xp} XP, and is level {level}.",
+            user.id
+        ))
+        .color(crate::THEME_COLOR)
+        .build();
+    Ok(ephemeral_embed_response(embed))
+}
+
+/// Changes a user's XP to whatever `change` returns for their current XP, and logs it.
+/// XP never goes below zero. `change` returns `None` if the new XP would overflow.
+async fn change_xp(
+    user_id: Id<UserMarker>,
+    guild_id: Id<GuildMarker>,
+    state: &AppState,
+    change: impl FnOnce(i64) -> Option<i64>,
+) -> Result<i64, Error> {
+    let _timer = state.metrics.query_timer("change_xp");
+    let mut txn = state.db.begin().await?;
+    // FOR UPDATE can't lock a row that isn't there yet, so make sure it is first.
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "INSERT INTO levels (id, xp, guild) VALUES ($1, 0, $2) ON CONFLICT (id, guild) DO NOTHING",
+        user_id.get() as i64,
+        guild_id.get() as i64
+    )
+    .execute(&mut txn)
+    .await?;
+    #[allow(clippy::cast_possible_wrap)]
+    let old = query!(
+        "SELECT xp FROM levels WHERE id = $1 AND guild = $2 FOR UPDATE",
+        user_id.get() as i64,
+        guild_id.get() as i64
+    )
+    .fetch_one(&mut txn)
+    .await?
+    .xp;
+    let new = change(old).ok_or(Error::TooMuchXp)?.max(0);
+    #[allow(clippy::cast_possible_wrap)]
+    query!(
+        "UPDATE levels SET xp = $1 WHERE id = $2 AND guild = $3",
+        new,
+        user_id.get() as i64,
+        guild_id.get() as i64
+    )
+    .execute(&mut txn)
+    .await?;
+    crate::events::record(&mut txn, guild_id, user_id, new - old, XpSource::Admin).await?;
+    txn.commit().await?;
+    Ok(new)
+}
+
+// Resetting can't be undone, so we don't do anything until the button is clicked.
+// The target is stored in the button's custom ID, just like the leaderboard does with pages.
+fn reset_prompt(reset: &XpReset) -> InteractionResponse {
+    let (target, description) = reset.user.as_ref().map_or_else(
+        || {
+            (
+                RESET_EVERYONE.to_string(),
+                "Are you sure you want to reset XP for **everyone** in this server?".to_string(),
+            )
+        },
+        |user| {
+            (
+                user.id.to_string(),
+                format!("Are you sure you want to reset XP for <@{}>?", user.id),
+            )
+        },
+    );
+    let confirm_button = Component::Button(Button {
+        custom_id: Some(format!("{RESET_CONFIRM_PREFIX}{target}")),
+        disabled: false,
+        emoji: None,
+        label: Some("Reset".to_string()),
+        style: ButtonStyle::Danger,
+        url: None,
+    });
+    let cancel_button = Component::Button(Button {
+        custom_id: Some(RESET_CANCEL_ID.to_string()),
+        disabled: false,
+        emoji: None,
+        label: Some("Cancel".to_string()),
+        style: ButtonStyle::Secondary,
+        url: None,
+    });
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    InteractionResponse {
+        kind: InteractionResponseType::ChannelMessageWithSource,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .components([Component::ActionRow(ActionRow {
+                    components: vec![confirm_button, cancel_button],
+                })])
+                .embeds([embed])
+                .flags(MessageFlags::EPHEMERAL)
+                .build(),
+        ),
+    }
+}
+
+pub async fn process_reset_component(
+    data: MessageComponentInteractionData,
+    guild_id: Id<GuildMarker>,
+    state: AppState,
+) -> Result<InteractionResponse, Error> {
+    let description = if data.custom_id == RESET_CANCEL_ID {
+        "Reset cancelled, nothing was changed.".to_string()
+    } else {
+        let target = data
+            .custom_id
+            .strip_prefix(RESET_CONFIRM_PREFIX)
+            .ok_or(Error::InvalidCustomButtonId)?;
+        #[allow(clippy::cast_possible_wrap)]
+        if target == RESET_EVERYONE {
+            // every deleted row gets an event taking away all its XP
+            let deleted = query!(
+                "WITH deleted AS (DELETE FROM levels WHERE guild = $1 RETURNING id, xp)
+                 INSERT INTO xp_events (guild, id, delta, source) SELECT $1, id, -xp, $2 FROM deleted",
+                guild_id.get() as i64,
+                XpSource::Admin.as_str()
+            )
+            .execute(&state.db)
+            .await?
+            .rows_affected();
+            format!("Reset XP for {deleted} users.")
+        } else {
+            let user_id: Id<UserMarker> =
+                Id::new_checked(target.parse()?).ok_or(Error::InvalidCustomButtonId)?;
+            query!(
+                "WITH deleted AS (DELETE FROM levels WHERE id = $1 AND guild = $2 RETURNING id, xp)
+                 INSERT INTO xp_events (guild, id, delta, source) SELECT $2, id, -xp, $3 FROM deleted",
+                user_id.get() as i64,
+                guild_id.get() as i64,
+                XpSource::Admin.as_str()
+            )
+            .execute(&state.db)
+            .await?;
+            format!("Reset XP for <@{user_id}>.")
+        }
+    };
+    let embed = EmbedBuilder::new()
+        .description(description)
+        .color(crate::THEME_COLOR)
+        .build();
+    // Replace the prompt so the buttons can't be clicked twice
+    Ok(InteractionResponse {
+        kind: InteractionResponseType::UpdateMessage,
+        data: Some(
+            InteractionResponseDataBuilder::new()
+                .components([])
+                .embeds([embed])
+                .build(),
+        ),
+    })
+}